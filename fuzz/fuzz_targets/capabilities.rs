@@ -0,0 +1,34 @@
+//! Decode arbitrary platform capability data with descriptor sets
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use usbd_microsoft_os::os_20::dump::CapabilityDump;
+use usbd_microsoft_os::os_20::owned::Capabilities;
+use usbd_microsoft_os::os_20::Error;
+use usbd_microsoft_os::WindowsVersion;
+
+fuzz_target!(|input: (&[u8], Vec<&[u8]>)| {
+    let (data, descriptor_sets) = input;
+    let _ = CapabilityDump(data).to_string();
+
+    let Ok(infos) = Capabilities::parse_data(data) else { return };
+    assert_eq!(data.len(), 17 + 8 * infos.len());
+
+    let Ok(capabilities) = Capabilities::from_data(data, &descriptor_sets) else { return };
+    // Versions lower than minimal and more than 29 sets are decoded but cannot be encoded
+    let encoded = match capabilities.data_to_vec() {
+        Ok(encoded) => encoded,
+        Err(Error::InvalidVersion) => {
+            assert!(infos.iter().any(|info| info.version.value() < WindowsVersion::MINIMAL.value()));
+            return;
+        },
+        Err(Error::TooLarge) => {
+            assert!(infos.len() > 29);
+            return;
+        },
+        Err(e) => panic!("unexpected error {:?}", e),
+    };
+    // Vendor codes and lengths have been verified, so only bReserved can differ
+    assert_eq!(Capabilities::parse_data(&encoded), Ok(infos));
+});
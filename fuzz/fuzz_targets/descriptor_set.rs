@@ -0,0 +1,21 @@
+//! Decode arbitrary data as a descriptor set
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use usbd_microsoft_os::lint;
+use usbd_microsoft_os::os_20::dump::DescriptorSetDump;
+use usbd_microsoft_os::os_20::owned::DescriptorSet;
+
+fuzz_target!(|data: &[u8]| {
+    // Dump must handle invalid data too
+    let _ = DescriptorSetDump(data).to_string();
+
+    let Ok(set) = DescriptorSet::from_bytes(data) else { return };
+    lint::lint(&set);
+    // Versions lower than minimal are decoded but cannot be encoded
+    let Ok(encoded) = set.to_bytes() else { return };
+    // Encoded data can only differ in reserved fields
+    assert_eq!(encoded.len(), data.len());
+    assert_eq!(DescriptorSet::from_bytes(&encoded).as_ref(), Ok(&set));
+});
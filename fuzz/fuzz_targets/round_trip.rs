@@ -0,0 +1,77 @@
+//! Encode arbitrary descriptor set, decode it and encode again
+
+#![no_main]
+
+use libfuzzer_sys::arbitrary::{Result, Unstructured};
+use libfuzzer_sys::fuzz_target;
+use usbd_microsoft_os::os_20::owned::{ConfigurationSubset, DescriptorSet, FeatureDescriptor, FunctionSubset};
+use usbd_microsoft_os::os_20::{Error, PropertyDataType};
+use usbd_microsoft_os::WindowsVersion;
+
+const VERSIONS: [WindowsVersion; 4] =
+    [WindowsVersion::WinBlue, WindowsVersion::Win10, WindowsVersion::Win10Rs1, WindowsVersion::Win10Rs5];
+
+const DATA_TYPES: [PropertyDataType; 7] = [
+    PropertyDataType::RegSz,
+    PropertyDataType::RegExpandSz,
+    PropertyDataType::RegBinary,
+    PropertyDataType::RegDwordLittleEndian,
+    PropertyDataType::RegDwordBigEndian,
+    PropertyDataType::RegLink,
+    PropertyDataType::RegMutliSz,
+];
+
+fn feature(u: &mut Unstructured) -> Result<FeatureDescriptor> {
+    Ok(match u.int_in_range(0..=5)? {
+        0 => FeatureDescriptor::CompatibleId { id: u.arbitrary()?, sub_id: u.arbitrary()? },
+        1 => FeatureDescriptor::RegistryProperty {
+            data_type: *u.choose(&DATA_TYPES)?,
+            name: u.arbitrary()?,
+            data: u.arbitrary()?,
+        },
+        2 => FeatureDescriptor::ResumeTime { recovery: u.arbitrary()?, signaling: u.arbitrary()? },
+        3 => FeatureDescriptor::ModelId { id: u.arbitrary()? },
+        4 => FeatureDescriptor::CcgpDevice,
+        _ => FeatureDescriptor::VendorRevision { revision: u.arbitrary()? },
+    })
+}
+
+fn list<T>(u: &mut Unstructured, item: fn(&mut Unstructured) -> Result<T>) -> Result<Vec<T>> {
+    let len = u.int_in_range(0..=4)?;
+    (0..len).map(|_| item(u)).collect()
+}
+
+fn function(u: &mut Unstructured) -> Result<FunctionSubset> {
+    Ok(FunctionSubset { first_interface: u.arbitrary()?, features: list(u, feature)? })
+}
+
+fn configuration(u: &mut Unstructured) -> Result<ConfigurationSubset> {
+    Ok(ConfigurationSubset {
+        configuration: u.arbitrary()?,
+        features: list(u, feature)?,
+        functions: list(u, function)?,
+    })
+}
+
+fn descriptor_set(u: &mut Unstructured) -> Result<DescriptorSet> {
+    Ok(DescriptorSet {
+        version: *u.choose(&VERSIONS)?,
+        features: list(u, feature)?,
+        configurations: list(u, configuration)?,
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(set) = descriptor_set(&mut Unstructured::new(data)) else { return };
+    // Sets with wTotalLength not fitting in 16 bits must be rejected by the encoder
+    if set.size() > u16::MAX as usize {
+        assert_eq!(set.to_bytes(), Err(Error::TooLarge));
+        return;
+    }
+
+    let encoded = set.to_bytes().expect("valid set must be encodable");
+    assert_eq!(encoded.len(), set.size());
+    let decoded = DescriptorSet::from_bytes(&encoded).expect("encoded set must be decodable");
+    assert_eq!(decoded, set);
+    assert_eq!(decoded.to_bytes().as_ref(), Ok(&encoded));
+});
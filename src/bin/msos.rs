@@ -0,0 +1,452 @@
+//! Command-line tool for working with MS OS 2.0 descriptors
+//!
+//! Descriptors can be given either as definition files (`.toml`/`.json`, see
+//! [`usbd_microsoft_os::build`]) or as raw descriptor set data, binary or hex-encoded.
+
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use usbd_microsoft_os::build::{Definition, DescriptorSetDefinition};
+use usbd_microsoft_os::c_header;
+use usbd_microsoft_os::codegen;
+use usbd_microsoft_os::inf::{self, InfOptions};
+use usbd_microsoft_os::lint::{self, Severity};
+use usbd_microsoft_os::os_20::dump::{CapabilityDump, DescriptorSetDump};
+use usbd_microsoft_os::os_20::owned::{self, CapabilitySetInfo};
+use usbd_microsoft_os::reg::{self, RegOptions};
+use usbd_microsoft_os::usbmon::{self, Decoded};
+use usbd_microsoft_os::WindowsVersion;
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// Encode, decode and validate Microsoft OS 2.0 descriptors
+#[derive(Parser)]
+#[command(name = "msos", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Decode raw descriptor set (or capability data) into a structured tree
+    Decode {
+        /// Binary or hex-encoded input file
+        input: PathBuf,
+        /// Decode BOS platform capability data instead of a descriptor set
+        #[arg(long)]
+        capability: bool,
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = TreeFormat::Toml)]
+        format: TreeFormat,
+    },
+    /// Check descriptor sets for problems, fails if any errors are found
+    Lint {
+        /// Definition file or raw descriptor set
+        input: PathBuf,
+    },
+    /// Encode definition into raw data, Rust source code or C header
+    Encode {
+        /// Definition file
+        input: PathBuf,
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = DataFormat::Hex)]
+        format: DataFormat,
+        /// Encode BOS platform capability data instead of a descriptor set (bin/hex only)
+        #[arg(long)]
+        capability: bool,
+        /// Index of descriptor set to encode (bin/hex only)
+        #[arg(long, default_value_t = 0)]
+        set: usize,
+        /// Prefix of macro and array names (c only)
+        #[arg(long, default_value = "msos")]
+        prefix: String,
+        /// Output file, stdout if not specified
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Generate WinUSB INF file
+    Inf {
+        /// Definition file or raw descriptor set
+        input: PathBuf,
+        /// Index of descriptor set
+        #[arg(long, default_value_t = 0)]
+        set: usize,
+        /// USB Vendor ID
+        #[arg(long, value_parser = parse_id)]
+        vid: u16,
+        /// USB Product ID
+        #[arg(long, value_parser = parse_id)]
+        pid: u16,
+        /// Manufacturer name
+        #[arg(long)]
+        manufacturer: String,
+        /// Device name
+        #[arg(long)]
+        name: String,
+        /// DriverVer value
+        #[arg(long, default_value = "01/01/2024,1.0.0.0")]
+        driver_ver: String,
+        /// Output file, stdout if not specified
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Generate .reg file with expected registry values
+    Reg {
+        /// Definition file or raw descriptor set
+        input: PathBuf,
+        /// Index of descriptor set
+        #[arg(long, default_value_t = 0)]
+        set: usize,
+        /// USB Vendor ID
+        #[arg(long, value_parser = parse_id)]
+        vid: u16,
+        /// USB Product ID
+        #[arg(long, value_parser = parse_id)]
+        pid: u16,
+        /// Device instance ID (usually serial number), used for values of the whole device
+        #[arg(long)]
+        instance: String,
+        /// Instance ID of a function in `<first interface>=<instance ID>` format, e.g.
+        /// `1=6&1a2b3c4d&0&0001` (generated by Windows, see "Device instance path" in Device
+        /// Manager), required for each function with registry properties
+        #[arg(long = "function-instance", value_parser = parse_function_instance)]
+        function_instances: Vec<(u8, String)>,
+        /// Only values of the function with this first interface
+        #[arg(long)]
+        interface: Option<u8>,
+        /// Output file, stdout if not specified
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Compare two descriptor sets structurally, fails if they differ
+    Diff {
+        /// Definition file or raw descriptor set
+        a: PathBuf,
+        /// Definition file or raw descriptor set
+        b: PathBuf,
+        /// Index of descriptor set in both inputs
+        #[arg(long, default_value_t = 0)]
+        set: usize,
+    },
+    /// Analyze MS OS descriptor requests in usbmon pcap/pcapng capture, fails if problems are found
+    Capture {
+        /// Capture file
+        input: PathBuf,
+        /// Print decoded descriptor sets
+        #[arg(short, long)]
+        verbose: bool,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TreeFormat {
+    Toml,
+    Json,
+    Debug,
+    /// Annotated hex dump, also works for invalid data
+    Dump,
+    /// Rust code with os_20 const structures
+    Rust,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DataFormat {
+    Bin,
+    Hex,
+    Rust,
+    /// C header with all descriptor sets and capability data, see `--prefix`
+    C,
+}
+
+/// Parse decimal or `0x`-prefixed hexadecimal ID
+fn parse_id(s: &str) -> std::result::Result<u16, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|e| e.to_string())
+}
+
+/// Parse `<first interface>=<instance ID>` pair
+fn parse_function_instance(s: &str) -> std::result::Result<(u8, String), String> {
+    let (interface, instance) = s.split_once('=').ok_or("expected <first interface>=<instance ID>")?;
+    let interface = interface.parse().map_err(|e: std::num::ParseIntError| e.to_string())?;
+    Ok((interface, instance.to_string()))
+}
+
+/// Parse hex string, bytes may be separated by whitespace/commas and prefixed with `0x`
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    let digits: String = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .map(|token| token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")).unwrap_or(token))
+        .collect();
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(digits.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn to_hex(data: &[u8]) -> String {
+    let lines: Vec<String> = data.chunks(16)
+        .map(|line| line.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" "))
+        .collect();
+    lines.join("\n") + "\n"
+}
+
+/// Read raw data, hex-encoded if the file is valid hex text, binary otherwise
+fn read_data(path: &Path) -> Result<Vec<u8>> {
+    Ok(decode_data(fs::read(path)?))
+}
+
+fn decode_data(data: Vec<u8>) -> Vec<u8> {
+    let hex = std::str::from_utf8(&data).ok().and_then(parse_hex);
+    hex.unwrap_or(data)
+}
+
+fn is_definition(path: &Path) -> bool {
+    matches!(path.extension().and_then(|ext| ext.to_str()), Some("toml" | "json"))
+}
+
+/// Load definition file or decode raw descriptor set
+fn load(path: &Path) -> Result<Definition> {
+    if is_definition(path) {
+        return Ok(Definition::load(path)?);
+    }
+    let set = owned::DescriptorSet::from_bytes(&read_data(path)?)?;
+    Ok(Definition { descriptor_sets: vec![DescriptorSetDefinition { alt_enum_cmd: 0, set }] })
+}
+
+fn load_set(path: &Path, index: usize) -> Result<owned::DescriptorSet> {
+    let mut def = load(path)?;
+    if index >= def.descriptor_sets.len() {
+        return Err(format!("{}: no descriptor set {}", path.display(), index).into());
+    }
+    Ok(def.descriptor_sets.swap_remove(index).set)
+}
+
+fn write_output(output: Option<&Path>, data: &[u8]) -> Result<()> {
+    match output {
+        Some(path) => fs::write(path, data)?,
+        None => std::io::stdout().write_all(data)?,
+    }
+    Ok(())
+}
+
+fn decode(input: &Path, capability: bool, format: TreeFormat) -> Result<()> {
+    let data = read_data(input)?;
+    if let TreeFormat::Dump = format {
+        match capability {
+            true => print!("{}", CapabilityDump(&data)),
+            false => print!("{}", DescriptorSetDump(&data)),
+        }
+        return Ok(());
+    }
+    if capability {
+        for (i, info) in owned::Capabilities::parse_data(&data)?.iter().enumerate() {
+            println!("[{}] {}", i, set_info(info));
+        }
+        return Ok(());
+    }
+    let set = owned::DescriptorSet::from_bytes(&data)?;
+    match format {
+        TreeFormat::Toml => {
+            let def = Definition { descriptor_sets: vec![DescriptorSetDefinition { alt_enum_cmd: 0, set }] };
+            print!("{}", toml::to_string(&def)?);
+        },
+        TreeFormat::Json => println!("{}", serde_json::to_string_pretty(&set)?),
+        TreeFormat::Debug | TreeFormat::Dump => println!("{:#?}", set),
+        TreeFormat::Rust => print!("{}", codegen::descriptor_set("DESCRIPTOR_SET", &set)?),
+    }
+    Ok(())
+}
+
+fn set_info(info: &CapabilitySetInfo) -> String {
+    let version = match info.version {
+        WindowsVersion::Other(value) => format!("0x{:08x}", value),
+        version => format!("{:?}", version),
+    };
+    format!("version={} total_len={} vendor_code={} alt_enum_cmd={}",
+        version, info.total_len, info.vendor_code, info.alt_enum_cmd)
+}
+
+fn capture(input: &Path, verbose: bool) -> Result<ExitCode> {
+    let mut problems = 0;
+    for transfer in usbmon::analyze(&fs::read(input)?)? {
+        println!("bus {} device {}: {} (bRequest={} wLength={} returned={})", transfer.bus, transfer.device,
+            transfer.kind, transfer.setup.request, transfer.setup.length, transfer.returned);
+        match &transfer.decoded {
+            Some(Decoded::Capability(infos)) => {
+                for info in infos {
+                    println!("  {}", set_info(info));
+                }
+            },
+            Some(Decoded::OsString { vendor_code }) => println!("  vendor_code={}", vendor_code),
+            Some(Decoded::DescriptorSet(set)) if verbose => println!("{:#?}", set),
+            _ => {},
+        }
+        for problem in &transfer.problems {
+            println!("  problem: {}", problem);
+        }
+        problems += transfer.problems.len();
+    }
+    Ok(if problems == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
+
+fn lint(input: &Path) -> Result<ExitCode> {
+    let def = load(input)?;
+    let mut errors = 0;
+    for (i, def) in def.descriptor_sets.iter().enumerate() {
+        for lint in lint::lint(&def.set) {
+            println!("set {}: {}", i, lint);
+            if lint.severity == Severity::Error {
+                errors += 1;
+            }
+        }
+    }
+    Ok(if errors == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
+
+fn encode(input: &Path, format: DataFormat, capability: bool, set: usize, prefix: &str, output: Option<&Path>) -> Result<()> {
+    let def = Definition::load(input)?;
+    let data = match (format, capability) {
+        (DataFormat::Rust, _) => return write_output(output, def.to_code()?.as_bytes()),
+        (DataFormat::C, _) => return write_output(output, c_header::header(&def.capabilities(), prefix)?.as_bytes()),
+        (_, true) => def.capabilities().data_to_vec()?,
+        (_, false) => match def.descriptor_sets.get(set) {
+            Some(def) => def.set.to_bytes()?,
+            None => return Err(format!("{}: no descriptor set {}", input.display(), set).into()),
+        },
+    };
+    match format {
+        DataFormat::Hex => write_output(output, to_hex(&data).as_bytes()),
+        _ => write_output(output, &data),
+    }
+}
+
+fn run(cli: Cli) -> Result<ExitCode> {
+    match cli.command {
+        Command::Decode { input, capability, format } => decode(&input, capability, format)?,
+        Command::Lint { input } => return lint(&input),
+        Command::Capture { input, verbose } => return capture(&input, verbose),
+        Command::Encode { input, format, capability, set, prefix, output } => {
+            encode(&input, format, capability, set, &prefix, output.as_deref())?
+        },
+        Command::Inf { input, set, vid, pid, manufacturer, name, driver_ver, output } => {
+            let options = InfOptions {
+                vid,
+                pid,
+                manufacturer: &manufacturer,
+                device_name: &name,
+                driver_ver: &driver_ver,
+            };
+            let inf = inf::generate(&load_set(&input, set)?, &options)?;
+            write_output(output.as_deref(), inf.as_bytes())?;
+        },
+        Command::Reg { input, set, vid, pid, instance, function_instances, interface, output } => {
+            let function_instances: Vec<_> = function_instances.iter()
+                .map(|(interface, instance)| (*interface, instance.as_str()))
+                .collect();
+            let options = RegOptions { vid, pid, interface, instance: &instance, function_instances: &function_instances };
+            let reg = reg::generate(&load_set(&input, set)?, &options)?;
+            write_output(output.as_deref(), reg.as_bytes())?;
+        },
+        Command::Diff { a, b, set } => {
+            let changes = load_set(&a, set)?.diff(&load_set(&b, set)?);
+            for change in &changes {
+                println!("{}", change);
+            }
+            if !changes.is_empty() {
+                return Ok(ExitCode::FAILURE);
+            }
+        },
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(2)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use usbd_microsoft_os::WindowsVersion;
+
+    // Descriptor set header only, WinBlue
+    const EMPTY_SET: [u8; 10] = [0x0a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x06, 0x0a, 0x00];
+
+    fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("msos-test-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(parse_hex("0a00ff"), Some(vec![0x0a, 0x00, 0xff]));
+        assert_eq!(parse_hex("0a 00\nFF\t"), Some(vec![0x0a, 0x00, 0xff]));
+        assert_eq!(parse_hex("0x0a, 0X00, 0xff"), Some(vec![0x0a, 0x00, 0xff]));
+        assert_eq!(parse_hex(""), Some(vec![]));
+        assert_eq!(parse_hex("0a0"), None);
+        assert_eq!(parse_hex("0g"), None);
+        assert_eq!(to_hex(&[0; 17]), format!("{}\n00\n", ["00"; 16].join(" ")));
+        assert_eq!(parse_hex(&to_hex(&EMPTY_SET)), Some(EMPTY_SET.to_vec()));
+    }
+
+    #[test]
+    fn function_instance() {
+        assert_eq!(parse_function_instance("1=6&1a2b3c4d&0&0001"), Ok((1, "6&1a2b3c4d&0&0001".into())));
+        assert!(parse_function_instance("6&1a2b3c4d&0&0001").is_err());
+        assert!(parse_function_instance("x=0001").is_err());
+    }
+
+    #[test]
+    fn data_format() {
+        assert_eq!(decode_data(b"0a 00 ff\n".to_vec()), [0x0a, 0x00, 0xff]);
+        // Binary data that is not hex text is left as is
+        assert_eq!(decode_data(EMPTY_SET.to_vec()), EMPTY_SET);
+        assert_eq!(decode_data(b"0a 0".to_vec()), b"0a 0");
+        assert_eq!(decode_data(vec![0xff, 0xfe]), [0xff, 0xfe]);
+    }
+
+    #[test]
+    fn load_raw_data() {
+        for (name, contents) in [("set.bin", EMPTY_SET.to_vec()), ("set.hex", to_hex(&EMPTY_SET).into_bytes())] {
+            let path = temp_file(name, &contents);
+            let def = load(&path);
+            fs::remove_file(&path).unwrap();
+            let def = def.unwrap();
+            assert_eq!(def.descriptor_sets.len(), 1);
+            assert_eq!(def.descriptor_sets[0].alt_enum_cmd, 0);
+            assert_eq!(def.descriptor_sets[0].set.version, WindowsVersion::WinBlue);
+            assert_eq!(def.descriptor_sets[0].set.to_bytes().unwrap(), EMPTY_SET);
+        }
+    }
+
+    #[test]
+    fn load_definition() {
+        let path = temp_file("set.toml", b"[[descriptor_sets]]\nversion = \"Win10\"\nalt_enum_cmd = 2\n");
+        let def = load(&path);
+        let set = load_set(&path, 1);
+        fs::remove_file(&path).unwrap();
+        let def = def.unwrap();
+        assert_eq!(def.descriptor_sets[0].alt_enum_cmd, 2);
+        assert_eq!(def.descriptor_sets[0].set.version, WindowsVersion::Win10);
+        assert!(set.unwrap_err().to_string().ends_with("set.toml: no descriptor set 1"));
+    }
+}
@@ -0,0 +1,243 @@
+//! Generate descriptors in `build.rs` from a TOML or JSON definition
+//!
+//! This allows to keep descriptor values (e.g. WinUSB GUIDs, registry properties) in a single
+//! configuration file shared with host-side software. Add this crate with `build` feature to
+//! `[build-dependencies]` and in `build.rs`:
+//!
+//! ```no_run
+//! let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("msos.rs");
+//! usbd_microsoft_os::build::generate("msos.toml", out).unwrap();
+//! ```
+//!
+//! Then include the generated items (see [`crate::codegen`]) in firmware code:
+//!
+//! ```ignore
+//! include!(concat!(env!("OUT_DIR"), "/msos.rs"));
+//!
+//! const fn class() -> MsOsUsbClass {
+//!     MsOsUsbClass::new(&CAPABILITIES_BYTES, DESCRIPTOR_SETS)
+//! }
+//! ```
+//!
+//! The definition contains a list of descriptor sets in the format of serialized
+//! [`crate::os_20::owned::DescriptorSet`], with optional `alt_enum_cmd`, e.g.
+//!
+//! ```toml
+//! [[descriptor_sets]]
+//! version = "WinBlue"
+//!
+//! [[descriptor_sets.configurations]]
+//! configuration = 0
+//!
+//! [[descriptor_sets.configurations.functions]]
+//! first_interface = 3
+//!
+//! [[descriptor_sets.configurations.functions.features]]
+//! type = "CompatibleId"
+//! id = "WINUSB"
+//!
+//! [[descriptor_sets.configurations.functions.features]]
+//! type = "RegistryProperty"
+//! data_type = "RegMultiSz"
+//! name = "DeviceInterfaceGUIDs"
+//! data = ["{6b09aac4-333f-4467-9e23-f88b9e9d95f7}"]
+//! ```
+
+use std::path::Path;
+use std::string::String;
+use std::vec::Vec;
+use std::{fs, io};
+
+use serde::{Deserialize, Serialize};
+
+use crate::codegen;
+use crate::os_20::{self, owned};
+
+/// Errors when loading a definition or generating code
+#[derive(Debug)]
+pub enum Error {
+    /// Could not read the definition or write the output
+    Io(io::Error),
+    /// Invalid TOML definition
+    Toml(toml::de::Error),
+    /// Invalid JSON definition
+    Json(serde_json::Error),
+    /// Definition file extension is neither `.toml` nor `.json`
+    UnknownFormat,
+    /// Descriptors cannot be generated
+    Descriptor(os_20::Error),
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {}", e),
+            Self::Toml(e) => write!(f, "invalid TOML definition: {}", e),
+            Self::Json(e) => write!(f, "invalid JSON definition: {}", e),
+            Self::UnknownFormat => f.write_str("unknown definition format, expected .toml or .json file"),
+            Self::Descriptor(e) => write!(f, "invalid descriptors: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<os_20::Error> for Error {
+    fn from(e: os_20::Error) -> Self {
+        Self::Descriptor(e)
+    }
+}
+
+/// Definition of all MS OS 2.0 descriptor sets of a device
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Definition {
+    /// Descriptor sets in the order of capability infos
+    pub descriptor_sets: Vec<DescriptorSetDefinition>,
+}
+
+/// Descriptor set with its capability information
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DescriptorSetDefinition {
+    /// bAltEnumCode, see [`os_20::CapabilityInfo::alt_enum_cmd`]
+    #[serde(default)]
+    pub alt_enum_cmd: u8,
+    /// Descriptor set
+    #[serde(flatten)]
+    pub set: owned::DescriptorSet,
+}
+
+impl Definition {
+    /// Parse definition in TOML format
+    pub fn from_toml(s: &str) -> Result<Self, Error> {
+        toml::from_str(s).map_err(Error::Toml)
+    }
+
+    /// Parse definition in JSON format
+    pub fn from_json(s: &str) -> Result<Self, Error> {
+        serde_json::from_str(s).map_err(Error::Json)
+    }
+
+    /// Load definition from a file, format is selected based on file extension
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let parse = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml,
+            Some("json") => Self::from_json,
+            _ => return Err(Error::UnknownFormat),
+        };
+        parse(&fs::read_to_string(path)?)
+    }
+
+    /// Get capabilities with all descriptor sets
+    pub fn capabilities(&self) -> owned::Capabilities {
+        owned::Capabilities {
+            infos: self.descriptor_sets.iter()
+                .map(|def| owned::CapabilityInfo {
+                    descriptors: def.set.clone(),
+                    alt_enum_cmd: def.alt_enum_cmd,
+                })
+                .collect(),
+        }
+    }
+
+    /// Generate Rust code, see [`codegen::capabilities`]
+    pub fn to_code(&self) -> Result<String, Error> {
+        Ok(codegen::capabilities(&self.capabilities())?)
+    }
+}
+
+/// Generate Rust code from definition in `input` file and write it to `output` file
+///
+/// Meant to be called from `build.rs`, prints `cargo:rerun-if-changed` for the input file.
+pub fn generate(input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<(), Error> {
+    let input = input.as_ref();
+    println!("cargo:rerun-if-changed={}", input.display());
+    let code = Definition::load(input)?.to_code()?;
+    fs::write(output, code)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os_20::PropertyDataType;
+    use crate::os_20::tests::EXAMPLE_SET;
+    use crate::WindowsVersion;
+
+    const WINUSB_TOML: &str = r#"
+[[descriptor_sets]]
+version = "WinBlue"
+
+[[descriptor_sets.configurations]]
+configuration = 0
+
+[[descriptor_sets.configurations.functions]]
+first_interface = 3
+
+[[descriptor_sets.configurations.functions.features]]
+type = "CompatibleId"
+id = "WINUSB"
+
+[[descriptor_sets.configurations.functions.features]]
+type = "RegistryProperty"
+data_type = "RegMultiSz"
+name = "DeviceInterfaceGUIDs"
+data = ["{6b09aac4-333f-4467-9e23-f88b9e9d95f7}"]
+"#;
+
+    #[test]
+    fn parse_toml() {
+        let def = Definition::from_toml(WINUSB_TOML).unwrap();
+        assert_eq!(def.descriptor_sets.len(), 1);
+        assert_eq!(def.descriptor_sets[0].alt_enum_cmd, 0);
+        let set = &def.descriptor_sets[0].set;
+        assert_eq!(set.version, WindowsVersion::WinBlue);
+        assert_eq!(set.configurations[0].functions[0].features, [
+            owned::FeatureDescriptor::CompatibleId { id: *b"WINUSB\0\0", sub_id: [0; 8] },
+            owned::FeatureDescriptor::RegistryProperty {
+                data_type: PropertyDataType::RegMutliSz,
+                name: "DeviceInterfaceGUIDs\0".encode_utf16().collect(),
+                data: "{6b09aac4-333f-4467-9e23-f88b9e9d95f7}\0\0".encode_utf16()
+                    .flat_map(u16::to_le_bytes)
+                    .collect(),
+            },
+        ]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let def = Definition {
+            descriptor_sets: vec![DescriptorSetDefinition {
+                alt_enum_cmd: 2,
+                set: owned::DescriptorSet::from(&EXAMPLE_SET),
+            }],
+        };
+        let json = serde_json::to_string(&def).unwrap();
+        assert_eq!(Definition::from_json(&json).unwrap(), def);
+        let toml = toml::to_string(&def).unwrap();
+        assert_eq!(Definition::from_toml(&toml).unwrap(), def);
+    }
+
+    #[test]
+    fn invalid_definitions() {
+        assert!(matches!(Definition::from_toml("descriptor_sets = 1"), Err(Error::Toml(_))));
+        assert!(matches!(Definition::from_json("{}"), Err(Error::Json(_))));
+        assert!(matches!(Definition::load("msos.yaml"), Err(Error::UnknownFormat)));
+
+        let def = Definition::from_toml(&WINUSB_TOML.replace("WinBlue", "Win8")).unwrap();
+        assert!(matches!(def.to_code(), Err(Error::Descriptor(os_20::Error::InvalidVersion))));
+    }
+
+    #[test]
+    fn generate_code() {
+        let code = Definition::from_toml(WINUSB_TOML).unwrap().to_code().unwrap();
+        assert!(code.contains("pub const DESCRIPTOR_SETS: &[&[u8]] = &[&DESCRIPTOR_SET_0_BYTES];"));
+        assert!(code.contains("utf16_null_le_bytes!(\"{6b09aac4-333f-4467-9e23-f88b9e9d95f7}\\0\")"));
+    }
+}
@@ -0,0 +1,224 @@
+//! Generate C headers with raw descriptor data
+//!
+//! Firmware written in C (e.g. a bootloader) can serve the same descriptors as the Rust
+//! application by including the generated header. The arrays are produced by the same encoder
+//! as [`crate::os_20::DescriptorSet::descriptor`] and [`crate::os_20::Capabilities::descriptor_data`]
+//! and are annotated with field names. For each descriptor set `<i>` the following are generated
+//! (with `MSOS` prefix):
+//!
+//! * `MSOS_DESCRIPTOR_SET_<i>_LEN`, `MSOS_DESCRIPTOR_SET_<i>_VENDOR_CODE` - length macros and
+//!   bMS_VendorCode used to request the descriptor set
+//! * `msos_descriptor_set_<i>` - descriptor set data
+//!
+//! And for the platform capability: `MSOS_CAPABILITIES_LEN` and `msos_capabilities` with its
+//! data (without bLength, bDescriptorType and bDevCapabilityType).
+
+use core::fmt::{self, Write};
+use std::string::String;
+
+use crate::os_20::dump::{self, Field, Sink};
+use crate::os_20::owned;
+use crate::os_20::Error;
+
+/// Errors when generating C header
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HeaderError {
+    /// Prefix is not a valid C identifier
+    InvalidPrefix,
+    /// Descriptors cannot be generated
+    Descriptor(Error),
+}
+
+impl core::fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidPrefix => f.write_str("prefix must be a C identifier (ASCII letters, digits and underscores)"),
+            Self::Descriptor(e) => write!(f, "invalid descriptors: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+impl From<Error> for HeaderError {
+    fn from(e: Error) -> Self {
+        Self::Descriptor(e)
+    }
+}
+
+/// Check that prefix forms valid C identifiers, i.e. consists of ASCII letters, digits and
+/// underscores and does not start with a digit
+fn is_identifier(prefix: &str) -> bool {
+    prefix.bytes().next().is_some_and(|b| !b.is_ascii_digit())
+        && prefix.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+/// Sink writing annotated C array elements
+struct ArrayElements {
+    out: String,
+}
+
+impl Sink for ArrayElements {
+    fn line(&mut self, _offset: usize, bytes: &[u8], field: Option<Field<'_>>) -> fmt::Result {
+        let mut line = String::from("    ");
+        for b in bytes {
+            write!(line, "0x{:02x}, ", b)?;
+        }
+        if let Some(field) = field {
+            write!(line, "{:1$}/* {2:3$}{4}", "", 4 + 6 * 8 - line.len(), "", 2 * field.depth, field.name)?;
+            if let Some(value) = field.value {
+                // Value must not end the comment
+                write!(line, ": {}", format!("{}", value).replace("*/", "*\\/"))?;
+            }
+            line += " */";
+        }
+        writeln!(self.out, "{}", line.trim_end())
+    }
+}
+
+fn array(out: &mut String, name: &str, len_macro: &str, elements: ArrayElements) {
+    writeln!(out, "static const uint8_t {}[{}] = {{", name, len_macro).unwrap();
+    out.push_str(&elements.out);
+    writeln!(out, "}};").unwrap();
+}
+
+/// Generate C header with data of all descriptor sets and the platform capability
+///
+/// `prefix` is used for macro names (upper case) and array names (lower case), as well as for
+/// the include guard (`<PREFIX>_H`). Returns [`HeaderError::InvalidPrefix`] if it is not a valid
+/// C identifier.
+pub fn header(capabilities: &owned::Capabilities, prefix: &str) -> Result<String, HeaderError> {
+    if !is_identifier(prefix) {
+        return Err(HeaderError::InvalidPrefix);
+    }
+    let upper = prefix.to_uppercase();
+    let lower = prefix.to_lowercase();
+    let data = capabilities.data_to_vec()?;
+
+    let mut out = String::new();
+    writeln!(out, "/* Generated from MS OS 2.0 descriptors by usbd-microsoft-os */").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "#ifndef {}_H", upper).unwrap();
+    writeln!(out, "#define {}_H", upper).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "#include <stdint.h>").unwrap();
+
+    for (i, info) in capabilities.infos.iter().enumerate() {
+        // Number of infos is limited by the capability length checked above
+        let vendor_code = crate::os_20::Capabilities::vendor_code_descriptor_set(i as u8);
+        let mut elements = ArrayElements { out: String::new() };
+        dump::descriptor_set(&mut elements, &info.descriptors.to_bytes()?).unwrap();
+        let len_macro = format!("{}_DESCRIPTOR_SET_{}_LEN", upper, i);
+        writeln!(out).unwrap();
+        writeln!(out, "/* MS OS 2.0 descriptor set {} */", i).unwrap();
+        writeln!(out, "#define {} {}", len_macro, info.descriptors.size()).unwrap();
+        writeln!(out, "#define {}_DESCRIPTOR_SET_{}_VENDOR_CODE {}", upper, i, vendor_code).unwrap();
+        writeln!(out).unwrap();
+        array(&mut out, &format!("{}_descriptor_set_{}", lower, i), &len_macro, elements);
+    }
+
+    let mut elements = ArrayElements { out: String::new() };
+    dump::capability(&mut elements, &data).unwrap();
+    let len_macro = format!("{}_CAPABILITIES_LEN", upper);
+    writeln!(out).unwrap();
+    writeln!(out, "/* MS OS 2.0 platform capability data, without bLength, bDescriptorType and bDevCapabilityType */").unwrap();
+    writeln!(out, "#define {} {}", len_macro, data.len()).unwrap();
+    writeln!(out).unwrap();
+    array(&mut out, &format!("{}_capabilities", lower), &len_macro, elements);
+
+    writeln!(out).unwrap();
+    writeln!(out, "#endif /* {}_H */", upper).unwrap();
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os_20::tests::EXAMPLE_SET;
+    use crate::WindowsVersion;
+
+    fn capabilities() -> owned::Capabilities {
+        owned::Capabilities {
+            infos: vec![owned::CapabilityInfo { descriptors: (&EXAMPLE_SET).into(), alt_enum_cmd: 0 }],
+        }
+    }
+
+    #[test]
+    fn example_header() {
+        let header = header(&capabilities(), "msos").unwrap();
+        assert!(header.starts_with("/* Generated from MS OS 2.0 descriptors by usbd-microsoft-os */
+
+#ifndef MSOS_H
+#define MSOS_H
+
+#include <stdint.h>
+
+/* MS OS 2.0 descriptor set 0 */
+#define MSOS_DESCRIPTOR_SET_0_LEN 178
+#define MSOS_DESCRIPTOR_SET_0_VENDOR_CODE 1
+
+static const uint8_t msos_descriptor_set_0[MSOS_DESCRIPTOR_SET_0_LEN] = {
+    0x0a, 0x00,                                     /* wLength: 10 */
+    0x00, 0x00,                                     /* wDescriptorType: MS_OS_20_SET_HEADER_DESCRIPTOR */
+    0x00, 0x00, 0x03, 0x06,                         /* dwWindowsVersion: 0x06030000 (WinBlue) */
+    0xb2, 0x00,                                     /* wTotalLength: 178 */
+    0x08, 0x00,                                     /*   wLength: 8 */
+"));
+        assert!(header.contains("
+    0x01,                                           /*     bFirstInterface: 1 */
+    0x00,                                           /*     bReserved */
+"));
+        assert!(header.contains("
+    0x44, 0x00, 0x65, 0x00, 0x76, 0x00, 0x69, 0x00, /*     PropertyName: \"DeviceInterfaceGUIDs\\0\" */
+    0x63, 0x00, 0x65, 0x00, 0x49, 0x00, 0x6e, 0x00,
+"));
+        assert!(header.ends_with("
+/* MS OS 2.0 platform capability data, without bLength, bDescriptorType and bDevCapabilityType */
+#define MSOS_CAPABILITIES_LEN 25
+
+static const uint8_t msos_capabilities[MSOS_CAPABILITIES_LEN] = {
+    0x00,                                           /* bReserved */
+    0xdf, 0x60, 0xdd, 0xd8, 0x89, 0x45, 0xc7, 0x4c, /* PlatformCapabilityUUID: D8DD60DF-4589-4CC7-9CD2-659D9E648A9F (MS OS 2.0) */
+    0x9c, 0xd2, 0x65, 0x9d, 0x9e, 0x64, 0x8a, 0x9f,
+    0x00, 0x00, 0x03, 0x06,                         /* dwWindowsVersion: 0x06030000 (WinBlue) */
+    0xb2, 0x00,                                     /* wMSOSDescriptorSetTotalLength: 178 */
+    0x01,                                           /* bMS_VendorCode: 1 */
+    0x00,                                           /* bAltEnumCode: 0 */
+};
+
+#endif /* MSOS_H */
+"), "{}", header);
+    }
+
+    #[test]
+    fn comment_values() {
+        let mut caps = capabilities();
+        let owned::FeatureDescriptor::RegistryProperty { name, .. } =
+            &mut caps.infos[0].descriptors.configurations[0].functions[0].features[1] else { unreachable!() };
+        *name = "a*/b\0".encode_utf16().collect();
+        assert!(header(&caps, "X").unwrap().contains("/*     PropertyName: \"a*\\/b\\0\" */"));
+
+        caps.infos[0].descriptors.version = WindowsVersion::Win8;
+        assert_eq!(header(&caps, "X"), Err(HeaderError::Descriptor(Error::InvalidVersion)));
+    }
+
+    #[test]
+    fn prefix() {
+        assert!(header(&capabilities(), "_usb_msos2").unwrap().contains("#define _USB_MSOS2_CAPABILITIES_LEN 25\n"));
+        for prefix in ["", "2msos", "ms-os", "ms os", "msos*/", "ms\u{f3}s"] {
+            assert_eq!(header(&capabilities(), prefix), Err(HeaderError::InvalidPrefix), "{:?}", prefix);
+        }
+    }
+
+    #[test]
+    fn vendor_codes() {
+        let mut caps = capabilities();
+        caps.infos.push(caps.infos[0].clone());
+        let text = header(&caps, "msos").unwrap();
+        assert!(text.contains("#define MSOS_DESCRIPTOR_SET_0_VENDOR_CODE 1\n"));
+        assert!(text.contains("#define MSOS_DESCRIPTOR_SET_1_VENDOR_CODE 2\n"));
+
+        caps.infos = vec![caps.infos[0].clone(); 30];
+        assert_eq!(header(&caps, "msos"), Err(HeaderError::Descriptor(Error::TooLarge)));
+    }
+}
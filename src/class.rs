@@ -1,6 +1,48 @@
 use usb_device::class_prelude::*;
 
-use crate::os_20::{Capabilities, DescriptorIndex};
+use crate::os_20::{Capabilities, ConfigurationSubset, DescriptorIndex, DescriptorSet, Error};
+
+/// Size of usb-device's control buffer, depending on feature `control-buffer-256`
+pub const CONTROL_BUFFER_SIZE: usize = if cfg!(feature = "control-buffer-256") { 256 } else { 128 };
+
+/// Panic if any of the descriptor sets does not fit in usb-device's control buffer
+///
+/// Use in `const` context to get a compile time error when descriptor sets served by
+/// [`DynamicMsOsUsbClass`] would be rejected because of their size:
+///
+/// ```
+/// # use usbd_microsoft_os::{os_20, WindowsVersion};
+/// # const DESCRIPTOR_SET: os_20::DescriptorSet = os_20::DescriptorSet {
+/// #     version: WindowsVersion::MINIMAL,
+/// #     features: &[],
+/// #     configurations: &[],
+/// # };
+/// const CAPABILITIES: os_20::Capabilities = os_20::Capabilities {
+///     infos: &[
+///         os_20::CapabilityInfo {
+///             descriptors: &DESCRIPTOR_SET,
+///             alt_enum_cmd: os_20::ALT_ENUM_CODE_NOT_SUPPORTED,
+///         }
+///     ],
+/// };
+/// const _: () = usbd_microsoft_os::class::assert_fits_control_buffer(&CAPABILITIES);
+/// ```
+///
+/// This is not needed for [`MsOsUsbClass`] which does not use the control buffer.
+pub const fn assert_fits_control_buffer(capabilities: &Capabilities) {
+    let mut i = 0;
+    while i < capabilities.infos.len() {
+        if capabilities.infos[i].descriptors.size() > CONTROL_BUFFER_SIZE {
+            if cfg!(feature = "control-buffer-256") {
+                panic!("Descriptor set does not fit in usb-device control buffer (256 bytes)");
+            } else {
+                panic!("Descriptor set does not fit in usb-device control buffer (128 bytes), \
+                    consider enabling feature `control-buffer-256`");
+            }
+        }
+        i += 1;
+    }
+}
 
 /// USB class responsible for handling MS OS descriptor requests
 ///
@@ -9,49 +51,571 @@ use crate::os_20::{Capabilities, DescriptorIndex};
 /// For performance reasons all the descriptors should be statically generated arrays. Use
 /// [`crate::os_20::DescriptorSet::descriptor`] and
 /// [`crate::os_20::Capabilities::descriptor_data`] const functions to generate the descriptors.
+///
+/// Descriptor sets are sent directly from the `'static` data, split into as many packets
+/// as needed, so their size is not limited by usb-device's control buffer. Descriptor sets
+/// generated in runtime (e.g. with [`crate::os_20::DescriptorSet::write_to`]) can be served
+/// this way too, as long as they are not modified after being moved to static memory.
+/// Otherwise use [`DynamicMsOsUsbClass`].
+///
+/// Only Windows issues MS OS 2.0 descriptor requests, so [`MsOsUsbClass::host_requested_set`]
+/// can be used to detect that the device is connected to a Windows host.
+///
+/// The device must report USB revision 2.1 (bcdUSB 0x0210), otherwise the host will not read BOS
+/// descriptor. This is what usb-device reports by default, with usb-device 0.3 it must not be
+/// lowered using `UsbDeviceBuilder::usb_rev`.
 pub struct MsOsUsbClass {
     /// Capabilities data obtained from [`crate::os_20::Capabilities::descriptor_data`]
     pub os_20_capabilities_data: &'static [u8],
     /// Data for each descriptor obtained from [`crate::os_20::DescriptorSet::descriptor`]
     pub os_20_descriptor_sets: &'static [&'static [u8]],
+    requested_set: Option<usize>,
+}
+
+impl MsOsUsbClass {
+    /// Create new class from capabilities data and descriptor sets
+    ///
+    /// Descriptor sets must be in the same order as [`crate::os_20::Capabilities::infos`].
+    pub const fn new(
+        os_20_capabilities_data: &'static [u8],
+        os_20_descriptor_sets: &'static [&'static [u8]],
+    ) -> Self {
+        Self {
+            os_20_capabilities_data,
+            os_20_descriptor_sets,
+            requested_set: None,
+        }
+    }
+
+    /// Index of the descriptor set most recently sent to the host
+    ///
+    /// Host selects the descriptor set based on its Windows version, so a `Some` value means
+    /// that the host is Windows with version at least the one of the selected descriptor set.
+    /// The value is cleared on USB reset.
+    pub fn host_requested_set(&self) -> Option<usize> {
+        self.requested_set
+    }
 }
 
 impl<B: UsbBus> UsbClass<B> for MsOsUsbClass {
+    fn reset(&mut self) {
+        self.requested_set = None;
+    }
+
     fn get_bos_descriptors(&self, writer: &mut BosWriter) -> usb_device::Result<()> {
+        trace!("MS OS: writing BOS platform capability ({} bytes)", self.os_20_capabilities_data.len());
         writer.capability(Capabilities::CAPABILITY_TYPE, self.os_20_capabilities_data)
     }
 
     fn control_in(&mut self, xfer: ControlIn<B>) {
         let req = xfer.request();
 
-        // MS OS 2.0 get descriptors request
-        if req.request_type == control::RequestType::Vendor
-            && req.recipient == control::Recipient::Device
-            // && req.value == 0x00 // ignore just in case
-            && req.index == DescriptorIndex::Descriptor as u16
-        {
+        if is_descriptor_request(req) {
+            debug!("MS OS: descriptor set request: vendor_code={}, wIndex={}, wLength={}",
+                req.request, req.index, req.length);
+
             let descriptor_set = Capabilities::vendor_code_to_descriptor_set(req.request)
-                .and_then(|i| self.os_20_descriptor_sets.get(i).copied());
+                .and_then(|i| Some((i, *self.os_20_descriptor_sets.get(i)?)));
 
-            if let Some(set) = descriptor_set {
+            if let Some((i, set)) = descriptor_set {
+                debug!("MS OS: accepting with descriptor set {} ({} bytes)", i, set.len());
+                self.requested_set = Some(i);
                 xfer.accept_with_static(set).ok();
             } else {
+                warn!("MS OS: rejecting request for unknown vendor code {}", req.request);
                 xfer.reject().ok();
             }
         }
     }
 
     fn control_out(&mut self, xfer: ControlOut<B>) {
+        alt_enumeration_request(xfer);
+    }
+}
+
+/// USB class responsible for handling MS OS descriptor requests with non-static descriptor data
+///
+/// This works the same as [`MsOsUsbClass`] but the descriptor data can be stored in any type that
+/// implements `AsRef<[u8]>`, e.g. [`crate::os_20::DescriptorBuffer`] generated in runtime using
+/// [`crate::os_20::DescriptorSet::write_to`], or a borrowed `&[u8]`. The data can be modified
+/// between requests through the public fields.
+///
+/// Descriptor sets are copied into usb-device's control buffer, so each of them must fit in it
+/// (see [`CONTROL_BUFFER_SIZE`]). Requests for descriptor sets that do not fit are rejected, use
+/// [`assert_fits_control_buffer`] to detect this at compile time. Use [`MsOsUsbClass`] for larger
+/// descriptor sets.
+pub struct DynamicMsOsUsbClass<C, S, const N: usize> {
+    /// Capabilities data obtained from [`crate::os_20::Capabilities::write_data_to`]
+    pub os_20_capabilities_data: C,
+    /// Data for each descriptor obtained from [`crate::os_20::DescriptorSet::write_to`]
+    pub os_20_descriptor_sets: [S; N],
+    requested_set: Option<usize>,
+}
+
+impl<C: AsRef<[u8]>, S: AsRef<[u8]>, const N: usize> DynamicMsOsUsbClass<C, S, N> {
+    /// Create new class from capabilities data and descriptor sets
+    ///
+    /// Descriptor sets must be in the same order as [`crate::os_20::Capabilities::infos`].
+    pub const fn new(os_20_capabilities_data: C, os_20_descriptor_sets: [S; N]) -> Self {
+        Self {
+            os_20_capabilities_data,
+            os_20_descriptor_sets,
+            requested_set: None,
+        }
+    }
+
+    /// Index of the descriptor set most recently sent to the host
+    ///
+    /// See [`MsOsUsbClass::host_requested_set`].
+    pub fn host_requested_set(&self) -> Option<usize> {
+        self.requested_set
+    }
+}
+
+impl<B, C, S, const N: usize> UsbClass<B> for DynamicMsOsUsbClass<C, S, N>
+where
+    B: UsbBus,
+    C: AsRef<[u8]>,
+    S: AsRef<[u8]>,
+{
+    fn reset(&mut self) {
+        self.requested_set = None;
+    }
+
+    fn get_bos_descriptors(&self, writer: &mut BosWriter) -> usb_device::Result<()> {
+        let data = self.os_20_capabilities_data.as_ref();
+        trace!("MS OS: writing BOS platform capability ({} bytes)", data.len());
+        writer.capability(Capabilities::CAPABILITY_TYPE, data)
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
         let req = xfer.request();
 
-        // MS OS 2.0 set alternate enumeration command
-        if req.request_type == control::RequestType::Vendor
-            && req.recipient == control::Recipient::Device
-            && req.index == DescriptorIndex::SetAltEnumeration as u16
+        if is_descriptor_request(req) {
+            debug!("MS OS: descriptor set request: vendor_code={}, wIndex={}, wLength={}",
+                req.request, req.index, req.length);
+
+            let descriptor_set = Capabilities::vendor_code_to_descriptor_set(req.request)
+                .and_then(|i| Some((i, self.os_20_descriptor_sets.get(i)?.as_ref())));
+
+            if let Some((i, set)) = descriptor_set {
+                debug!("MS OS: accepting with descriptor set {} ({} bytes)", i, set.len());
+                if xfer.accept_with(set).is_ok() {
+                    self.requested_set = Some(i);
+                } else {
+                    warn!("MS OS: descriptor set {} does not fit in control buffer", i);
+                }
+            } else {
+                warn!("MS OS: rejecting request for unknown vendor code {}", req.request);
+                xfer.reject().ok();
+            }
+        }
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        alt_enumeration_request(xfer);
+    }
+}
+
+/// Check that function subsets use interface numbers allocated by usb-device
+///
+/// `interfaces` must contain the first interface of each function, in the same order as
+/// [`ConfigurationSubset::functions`], e.g. obtained from [`UsbBusAllocator::interface`] calls
+/// of other classes. This allows to detect hard-coded [`crate::os_20::FunctionSubset::first_interface`]
+/// values that no longer match after reordering classes.
+pub fn check_first_interfaces(
+    configuration: &ConfigurationSubset,
+    interfaces: &[InterfaceNumber],
+) -> Result<(), Error> {
+    if configuration.functions.len() != interfaces.len() {
+        return Err(Error::InterfaceMismatch);
+    }
+    for (function, interface) in configuration.functions.iter().zip(interfaces) {
+        if function.first_interface != u8::from(*interface) {
+            warn!("MS OS: function subset first interface {} does not match interface {}",
+                function.first_interface, u8::from(*interface));
+            return Err(Error::InterfaceMismatch);
+        }
+    }
+    Ok(())
+}
+
+/// Set first interface of function subsets in encoded descriptor set to the allocated interfaces
+///
+/// `descriptor_set` is the data of `set` (e.g. a [`crate::os_20::DescriptorBuffer`] to be used
+/// with [`DynamicMsOsUsbClass`]) and `interfaces` are as in [`check_first_interfaces`].
+/// All slots are validated before writing, so `descriptor_set` is left unmodified on errors.
+pub fn patch_first_interfaces(
+    set: &DescriptorSet,
+    configuration: usize,
+    descriptor_set: &mut [u8],
+    interfaces: &[InterfaceNumber],
+) -> Result<(), Error> {
+    let functions = set.configurations.get(configuration)
+        .ok_or(Error::InterfaceMismatch)?
+        .functions;
+    if functions.len() != interfaces.len() {
+        return Err(Error::InterfaceMismatch);
+    }
+    for function in 0..functions.len() {
+        let slot = set.first_interface_slot(configuration, function);
+        if slot.offset + slot.len > descriptor_set.len() {
+            return Err(Error::BufferTooSmall);
+        }
+    }
+    for (function, interface) in interfaces.iter().enumerate() {
+        set.first_interface_slot(configuration, function)
+            .apply(descriptor_set, &[u8::from(*interface)])?;
+    }
+    Ok(())
+}
+
+/// Check for MS OS 2.0 get descriptors request
+fn is_descriptor_request(req: &control::Request) -> bool {
+    req.request_type == control::RequestType::Vendor
+        && req.recipient == control::Recipient::Device
+        // && req.value == 0x00 // ignore just in case
+        && req.index == DescriptorIndex::Descriptor as u16
+}
+
+/// Handle MS OS 2.0 set alternate enumeration command
+fn alt_enumeration_request<B: UsbBus>(xfer: ControlOut<B>) {
+    let req = xfer.request();
+
+    if req.request_type == control::RequestType::Vendor
+        && req.recipient == control::Recipient::Device
+        && req.index == DescriptorIndex::SetAltEnumeration as u16
+    {
+        let alt_enum_code = req.value.to_le_bytes()[1];
+        debug!("MS OS: set alternate enumeration request: vendor_code={}, bAltEnumCode={}",
+            req.request, alt_enum_code);
+        // FIXME: not supported yet
+        warn!("MS OS: rejecting set alternate enumeration (not supported)");
+        xfer.reject().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::vec::Vec;
+
+    use usb_device::bus::PollResult;
+    use usb_device::device::{UsbDevice, UsbDeviceBuilder, UsbVidPid};
+    use usb_device::UsbDirection;
+
+    use super::*;
+    use crate::os_20::{CapabilityInfo, DescriptorSet, FeatureDescriptor, FunctionSubset, PropertyDataType};
+    use crate::WindowsVersion;
+
+    /// Bus that feeds SETUP packets to the control endpoint and collects data written to it
+    #[derive(Default)]
+    struct MockBus {
+        state: Mutex<MockState>,
+    }
+
+    #[derive(Default)]
+    struct MockState {
+        reset: bool,
+        setup: Option<[u8; 8]>,
+        in_complete: bool,
+        written: Vec<u8>,
+        stalled: bool,
+    }
+
+    impl UsbBus for MockBus {
+        fn alloc_ep(
+            &mut self,
+            ep_dir: UsbDirection,
+            ep_addr: Option<EndpointAddress>,
+            _ep_type: EndpointType,
+            _max_packet_size: u16,
+            _interval: u8,
+        ) -> usb_device::Result<EndpointAddress> {
+            Ok(ep_addr.unwrap_or(EndpointAddress::from_parts(1, ep_dir)))
+        }
+
+        fn enable(&mut self) {}
+
+        fn reset(&self) {}
+
+        fn set_device_address(&self, _addr: u8) {}
+
+        fn write(&self, _ep_addr: EndpointAddress, buf: &[u8]) -> usb_device::Result<usize> {
+            let mut state = self.state.lock().unwrap();
+            state.written.extend_from_slice(buf);
+            state.in_complete = true;
+            Ok(buf.len())
+        }
+
+        fn read(&self, _ep_addr: EndpointAddress, buf: &mut [u8]) -> usb_device::Result<usize> {
+            let setup = self.state.lock().unwrap().setup.take().ok_or(UsbError::WouldBlock)?;
+            buf[..setup.len()].copy_from_slice(&setup);
+            Ok(setup.len())
+        }
+
+        fn set_stalled(&self, _ep_addr: EndpointAddress, stalled: bool) {
+            self.state.lock().unwrap().stalled = stalled;
+        }
+
+        fn is_stalled(&self, _ep_addr: EndpointAddress) -> bool {
+            self.state.lock().unwrap().stalled
+        }
+
+        fn suspend(&self) {}
+
+        fn resume(&self) {}
+
+        fn poll(&self) -> PollResult {
+            let mut state = self.state.lock().unwrap();
+            if state.reset {
+                state.reset = false;
+                PollResult::Reset
+            } else if state.setup.is_some() || state.in_complete {
+                let ep_in_complete = state.in_complete as u16;
+                state.in_complete = false;
+                PollResult::Data { ep_out: 0, ep_in_complete, ep_setup: state.setup.is_some() as u16 }
+            } else {
+                PollResult::None
+            }
+        }
+    }
+
+    fn device(alloc: &UsbBusAllocator<MockBus>) -> UsbDevice<'_, MockBus> {
+        UsbDeviceBuilder::new(alloc, UsbVidPid(0x1234, 0x5678)).build()
+    }
+
+    /// Run control IN transfer, returns data sent or `None` if the request has been rejected
+    fn control_in(device: &mut UsbDevice<'_, MockBus>, class: &mut dyn UsbClass<MockBus>, setup: [u8; 8]) -> Option<Vec<u8>> {
         {
-            let _alt_enum_code = req.value.to_le_bytes()[1];
-            // FIXME: not supported yet
-            xfer.reject().ok();
+            let mut state = device.bus().state.lock().unwrap();
+            state.setup = Some(setup);
+            state.written.clear();
+            state.stalled = false;
+        }
+        while device.poll(&mut [&mut *class]) {}
+        let state = device.bus().state.lock().unwrap();
+        (!state.stalled).then(|| state.written.clone())
+    }
+
+    fn bus_reset(device: &mut UsbDevice<'_, MockBus>, class: &mut dyn UsbClass<MockBus>) {
+        device.bus().state.lock().unwrap().reset = true;
+        device.poll(&mut [class]);
+    }
+
+    /// MS OS 2.0 descriptor request for given vendor code
+    fn descriptor_request(vendor_code: u8) -> [u8; 8] {
+        let [index_lo, index_hi] = (DescriptorIndex::Descriptor as u16).to_le_bytes();
+        [0xc0, vendor_code, 0, 0, index_lo, index_hi, 0xff, 0xff]
+    }
+
+    const SMALL_SET_BYTES: [u8; SMALL_SET.size()] = SMALL_SET.descriptor();
+    const SMALL_SET_CAPABILITIES: Capabilities = Capabilities {
+        infos: &[CapabilityInfo { descriptors: &SMALL_SET, alt_enum_cmd: 0 }],
+    };
+    const SMALL_SET_CAPABILITIES_DATA: [u8; SMALL_SET_CAPABILITIES.data_len()] = SMALL_SET_CAPABILITIES.descriptor_data();
+
+    const SMALL_SET: DescriptorSet = DescriptorSet {
+        version: WindowsVersion::MINIMAL,
+        features: &[FeatureDescriptor::CcgpDevice],
+        configurations: &[],
+    };
+
+    const LARGE_SET: DescriptorSet = DescriptorSet {
+        version: WindowsVersion::MINIMAL,
+        features: &[FeatureDescriptor::RegistryProperty {
+            data_type: PropertyDataType::RegBinary,
+            name: &utf16_lit::utf16_null!("Data"),
+            data: &[0; 256],
+        }],
+        configurations: &[],
+    };
+    const LARGE_SET_BYTES: [u8; LARGE_SET.size()] = LARGE_SET.descriptor();
+    const LARGE_SET_CAPABILITIES: Capabilities = Capabilities {
+        infos: &[CapabilityInfo { descriptors: &LARGE_SET, alt_enum_cmd: 0 }],
+    };
+    const LARGE_SET_CAPABILITIES_DATA: [u8; LARGE_SET_CAPABILITIES.data_len()] = LARGE_SET_CAPABILITIES.descriptor_data();
+
+    #[test]
+    fn fits_control_buffer() {
+        const CAPABILITIES: Capabilities = Capabilities {
+            infos: &[CapabilityInfo { descriptors: &SMALL_SET, alt_enum_cmd: 0 }],
+        };
+        const _: () = assert_fits_control_buffer(&CAPABILITIES);
+    }
+
+    #[test]
+    #[should_panic]
+    fn does_not_fit_control_buffer() {
+        const CAPABILITIES: Capabilities = Capabilities {
+            infos: &[
+                CapabilityInfo { descriptors: &SMALL_SET, alt_enum_cmd: 0 },
+                CapabilityInfo { descriptors: &LARGE_SET, alt_enum_cmd: 0 },
+            ],
+        };
+        assert_fits_control_buffer(&CAPABILITIES);
+    }
+
+    #[test]
+    fn host_requested_set() {
+        let alloc = UsbBusAllocator::new(MockBus::default());
+        let mut device = device(&alloc);
+        let mut class = MsOsUsbClass::new(&SMALL_SET_CAPABILITIES_DATA, &[&SMALL_SET_BYTES]);
+        assert_eq!(class.host_requested_set(), None);
+
+        // Vendor code of a different descriptor set
+        let unknown = Capabilities::vendor_code_descriptor_set(1);
+        assert_eq!(control_in(&mut device, &mut class, descriptor_request(unknown)), None);
+        assert_eq!(class.host_requested_set(), None);
+
+        let vendor_code = Capabilities::vendor_code_descriptor_set(0);
+        let data = control_in(&mut device, &mut class, descriptor_request(vendor_code));
+        assert_eq!(data.as_deref(), Some(&SMALL_SET_BYTES[..]));
+        assert_eq!(class.host_requested_set(), Some(0));
+
+        bus_reset(&mut device, &mut class);
+        assert_eq!(class.host_requested_set(), None);
+    }
+
+    #[test]
+    fn dynamic_host_requested_set() {
+        let alloc = UsbBusAllocator::new(MockBus::default());
+        let mut device = device(&alloc);
+        let mut class = DynamicMsOsUsbClass::new(SMALL_SET_CAPABILITIES_DATA, [SMALL_SET_BYTES]);
+        assert_eq!(class.host_requested_set(), None);
+
+        let unknown = Capabilities::vendor_code_descriptor_set(1);
+        assert_eq!(control_in(&mut device, &mut class, descriptor_request(unknown)), None);
+        assert_eq!(class.host_requested_set(), None);
+
+        let vendor_code = Capabilities::vendor_code_descriptor_set(0);
+        let data = control_in(&mut device, &mut class, descriptor_request(vendor_code));
+        assert_eq!(data.as_deref(), Some(&SMALL_SET_BYTES[..]));
+        assert_eq!(class.host_requested_set(), Some(0));
+
+        bus_reset(&mut device, &mut class);
+        assert_eq!(class.host_requested_set(), None);
+    }
+
+    #[test]
+    fn large_set() {
+        // Larger than the control buffer, also with `control-buffer-256`
+        const _: () = assert!(LARGE_SET.size() > CONTROL_BUFFER_SIZE);
+        let alloc = UsbBusAllocator::new(MockBus::default());
+        let mut device = device(&alloc);
+        let mut class = MsOsUsbClass::new(&LARGE_SET_CAPABILITIES_DATA, &[&LARGE_SET_BYTES]);
+
+        let vendor_code = Capabilities::vendor_code_descriptor_set(0);
+        let data = control_in(&mut device, &mut class, descriptor_request(vendor_code));
+        assert_eq!(data.as_deref(), Some(&LARGE_SET_BYTES[..]));
+        assert_eq!(class.host_requested_set(), Some(0));
+    }
+
+    #[test]
+    fn dynamic_large_set() {
+        let alloc = UsbBusAllocator::new(MockBus::default());
+        let mut device = device(&alloc);
+        let mut class = DynamicMsOsUsbClass::new(LARGE_SET_CAPABILITIES_DATA, [LARGE_SET_BYTES]);
+
+        // Does not fit in the control buffer, so the request is stalled
+        let vendor_code = Capabilities::vendor_code_descriptor_set(0);
+        assert_eq!(control_in(&mut device, &mut class, descriptor_request(vendor_code)), None);
+        assert_eq!(class.host_requested_set(), None);
+    }
+
+    macro_rules! composite_set {
+        ($first:literal, $second:literal) => {
+            DescriptorSet {
+                version: WindowsVersion::MINIMAL,
+                features: &[],
+                configurations: &[ConfigurationSubset {
+                    configuration: 0,
+                    features: &[],
+                    functions: &[
+                        FunctionSubset { first_interface: $first, features: &[FeatureDescriptor::CcgpDevice] },
+                        FunctionSubset { first_interface: $second, features: &[] },
+                    ],
+                }],
+            }
+        };
+    }
+
+    #[test]
+    fn check_interfaces() {
+        const SET: DescriptorSet = composite_set!(0, 2);
+        let alloc = UsbBusAllocator::new(MockBus::default());
+        let interfaces = [alloc.interface(), alloc.interface(), alloc.interface()];
+
+        let config = &SET.configurations[0];
+        assert_eq!(check_first_interfaces(config, &[interfaces[0], interfaces[2]]), Ok(()));
+        assert_eq!(check_first_interfaces(config, &[interfaces[0], interfaces[1]]), Err(Error::InterfaceMismatch));
+        assert_eq!(check_first_interfaces(config, &[interfaces[2], interfaces[0]]), Err(Error::InterfaceMismatch));
+        assert_eq!(check_first_interfaces(config, &[interfaces[0]]), Err(Error::InterfaceMismatch));
+    }
+
+    #[test]
+    fn patch_interfaces() {
+        const SET: DescriptorSet = composite_set!(0, 0);
+        const PATCHED: [u8; SET.size()] = composite_set!(1, 2).descriptor();
+        let alloc = UsbBusAllocator::new(MockBus::default());
+        let interfaces = [alloc.interface(), alloc.interface(), alloc.interface()];
+
+        let mut data: [u8; SET.size()] = SET.descriptor();
+        patch_first_interfaces(&SET, 0, &mut data, &interfaces[1..]).unwrap();
+        assert_eq!(data, PATCHED);
+        assert_eq!(data[SET.first_interface_slot(0, 0).offset], 1);
+        assert_eq!(data[SET.first_interface_slot(0, 1).offset], 2);
+
+        // Data is left untouched on errors
+        let mut data: [u8; SET.size()] = SET.descriptor();
+        assert_eq!(patch_first_interfaces(&SET, 0, &mut data, &interfaces), Err(Error::InterfaceMismatch));
+        assert_eq!(patch_first_interfaces(&SET, 1, &mut data, &interfaces[1..]), Err(Error::InterfaceMismatch));
+        assert_eq!(data, SET.descriptor::<{ SET.size() }>());
+
+        // Truncated before the second function, the first one must not be patched either
+        let truncated = &mut data[..SET.first_interface_slot(0, 1).offset];
+        assert_eq!(patch_first_interfaces(&SET, 0, truncated, &interfaces[1..]), Err(Error::BufferTooSmall));
+        assert_eq!(data, SET.descriptor::<{ SET.size() }>());
+    }
+
+    #[test]
+    fn bos_descriptor() {
+        let alloc = UsbBusAllocator::new(MockBus::default());
+        let mut device = device(&alloc);
+        let mut class = MsOsUsbClass::new(&SMALL_SET_CAPABILITIES_DATA, &[&SMALL_SET_BYTES]);
+
+        // GET_DESCRIPTOR(DEVICE), Windows only reads BOS descriptor with bcdUSB >= 0x0201
+        let desc = control_in(&mut device, &mut class, [0x80, 6, 0, 1, 0, 0, 18, 0]).unwrap();
+        assert_eq!(u16::from_le_bytes([desc[2], desc[3]]), 0x0210);
+
+        // GET_DESCRIPTOR(BOS), 5-byte header followed by capabilities, including usb-device's own
+        let bos = control_in(&mut device, &mut class, [0x80, 6, 0, 15, 0, 0, 0xff, 0]).unwrap();
+        assert_eq!(u16::from_le_bytes([bos[2], bos[3]]) as usize, bos.len());
+        let mut capabilities = Vec::new();
+        let mut pos = 5;
+        while pos < bos.len() {
+            capabilities.push(&bos[pos..pos + bos[pos] as usize]);
+            pos += bos[pos] as usize;
         }
+        let platform = capabilities.iter()
+            .find(|c| c[1..3] == [0x10, Capabilities::CAPABILITY_TYPE])
+            .unwrap();
+        assert_eq!(platform[0] as usize, 3 + SMALL_SET_CAPABILITIES_DATA.len());
+        assert_eq!(platform[3..], SMALL_SET_CAPABILITIES_DATA);
+    }
+
+    /// With lower USB revision usb-device 0.3 does not send BOS descriptor at all
+    #[cfg(feature = "usb-device-03")]
+    #[test]
+    fn bos_descriptor_usb_200() {
+        let alloc = UsbBusAllocator::new(MockBus::default());
+        let mut device = UsbDeviceBuilder::new(&alloc, UsbVidPid(0x1234, 0x5678))
+            .usb_rev(usb_device::device::UsbRev::Usb200)
+            .build();
+        let mut class = MsOsUsbClass::new(&SMALL_SET_CAPABILITIES_DATA, &[&SMALL_SET_BYTES]);
+
+        let desc = control_in(&mut device, &mut class, [0x80, 6, 0, 1, 0, 0, 18, 0]).unwrap();
+        assert_eq!(u16::from_le_bytes([desc[2], desc[3]]), 0x0200);
+        assert_eq!(control_in(&mut device, &mut class, [0x80, 6, 0, 15, 0, 0, 0xff, 0]), None);
     }
 }
@@ -0,0 +1,355 @@
+//! Generate Rust source code with `os_20` const structures from owned descriptors
+//!
+//! The generated code can be included in firmware using [`include!`], e.g. from a build script
+//! output (see [`crate::build`]). For [`crate::os_20::owned::Capabilities`] the following items are generated:
+//!
+//! * `DESCRIPTOR_SET_<i>` - [`crate::os_20::DescriptorSet`] for each capability info
+//! * `DESCRIPTOR_SET_<i>_BYTES` - raw data of each descriptor set
+//! * `CAPABILITIES` - [`crate::os_20::Capabilities`] referencing all descriptor sets
+//! * `CAPABILITIES_BYTES` - raw capabilities data
+//! * `DESCRIPTOR_SETS` - slice of all descriptor sets data, as passed to `MsOsUsbClass::new`
+//!
+//! Strings are generated using [`utf16_lit::utf16_null`] and [`crate::utf16_null_le_bytes`]
+//! whenever possible, so the generated code stays readable.
+//!
+//! Code can also be generated from raw descriptor data, e.g. captured from a reference device,
+//! using [`crate::codegen::descriptor_set_from_bytes`] and [`crate::codegen::capabilities_from_bytes`].
+
+use core::fmt::Write;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+use crate::os_20::owned::{self, utf16_null_str, utf16_null_strings, DecodeError};
+use crate::os_20::{Error, PropertyDataType};
+use crate::WindowsVersion;
+
+/// Path to this crate used in generated code
+const CRATE: &str = "::usbd_microsoft_os";
+
+/// Errors when generating code from raw descriptor data
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FromBytesError {
+    /// Data could not be decoded
+    Decode(DecodeError),
+    /// Decoded descriptors cannot be generated
+    Descriptor(Error),
+}
+
+impl core::fmt::Display for FromBytesError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Decode(e) => write!(f, "invalid descriptor data: {}", e),
+            Self::Descriptor(e) => write!(f, "invalid descriptors: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FromBytesError {}
+
+impl From<DecodeError> for FromBytesError {
+    fn from(e: DecodeError) -> Self {
+        Self::Decode(e)
+    }
+}
+
+impl From<Error> for FromBytesError {
+    fn from(e: Error) -> Self {
+        Self::Descriptor(e)
+    }
+}
+
+/// Generate all items for given capabilities
+///
+/// Returns [`Error::InvalidVersion`] if any descriptor set uses version lower than
+/// [`crate::WindowsVersion::MINIMAL`], as the generated code would fail to compile.
+pub fn capabilities(capabilities: &owned::Capabilities) -> Result<String, Error> {
+    let mut out = String::new();
+    let mut names = Vec::new();
+
+    for (i, info) in capabilities.infos.iter().enumerate() {
+        let name = format!("DESCRIPTOR_SET_{}", i);
+        out += &descriptor_set(&name, &info.descriptors)?;
+        out += "\n";
+        names.push(name);
+    }
+
+    writeln!(out, "/// MS OS 2.0 platform capability").unwrap();
+    writeln!(out, "pub const CAPABILITIES: {}::os_20::Capabilities = {}::os_20::Capabilities {{", CRATE, CRATE).unwrap();
+    writeln!(out, "    infos: &[").unwrap();
+    for (name, info) in names.iter().zip(&capabilities.infos) {
+        writeln!(out, "        {}::os_20::CapabilityInfo {{", CRATE).unwrap();
+        writeln!(out, "            descriptors: &{},", name).unwrap();
+        writeln!(out, "            alt_enum_cmd: {},", info.alt_enum_cmd).unwrap();
+        writeln!(out, "        }},").unwrap();
+    }
+    writeln!(out, "    ],").unwrap();
+    writeln!(out, "}};").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "/// Raw data of [`CAPABILITIES`]").unwrap();
+    writeln!(out, "pub const CAPABILITIES_BYTES: [u8; CAPABILITIES.data_len()] = CAPABILITIES.descriptor_data();").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "/// Raw data of all descriptor sets in the order of [`CAPABILITIES`] infos").unwrap();
+    let sets: Vec<_> = names.iter().map(|name| format!("&{}_BYTES", name)).collect();
+    writeln!(out, "pub const DESCRIPTOR_SETS: &[&[u8]] = &[{}];", sets.join(", ")).unwrap();
+
+    Ok(out)
+}
+
+/// Generate `name` const descriptor set and `<name>_BYTES` with its raw data
+pub fn descriptor_set(name: &str, set: &owned::DescriptorSet) -> Result<String, Error> {
+    if !set.version.is_allowed() {
+        return Err(Error::InvalidVersion);
+    }
+
+    let mut out = String::new();
+    writeln!(out, "/// MS OS 2.0 descriptor set").unwrap();
+    writeln!(out, "pub const {}: {}::os_20::DescriptorSet = {}::os_20::DescriptorSet {{", name, CRATE, CRATE).unwrap();
+    match set.version {
+        WindowsVersion::Other(value) => writeln!(out, "    version: {}::WindowsVersion::Other(0x{:08x}),", CRATE, value),
+        version => writeln!(out, "    version: {}::WindowsVersion::{:?},", CRATE, version),
+    }.unwrap();
+    features(&mut out, 1, &set.features);
+    if set.configurations.is_empty() {
+        writeln!(out, "    configurations: &[],").unwrap();
+    } else {
+        writeln!(out, "    configurations: &[").unwrap();
+    }
+    for config in &set.configurations {
+        writeln!(out, "        {}::os_20::ConfigurationSubset {{", CRATE).unwrap();
+        writeln!(out, "            configuration: {},", config.configuration).unwrap();
+        features(&mut out, 3, &config.features);
+        if config.functions.is_empty() {
+            writeln!(out, "            functions: &[],").unwrap();
+        } else {
+            writeln!(out, "            functions: &[").unwrap();
+        }
+        for function in &config.functions {
+            writeln!(out, "                {}::os_20::FunctionSubset {{", CRATE).unwrap();
+            writeln!(out, "                    first_interface: {},", function.first_interface).unwrap();
+            features(&mut out, 5, &function.features);
+            writeln!(out, "                }},").unwrap();
+        }
+        if !config.functions.is_empty() {
+            writeln!(out, "            ],").unwrap();
+        }
+        writeln!(out, "        }},").unwrap();
+    }
+    if !set.configurations.is_empty() {
+        writeln!(out, "    ],").unwrap();
+    }
+    writeln!(out, "}};").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "/// Raw data of [`{}`]", name).unwrap();
+    writeln!(out, "pub const {name}_BYTES: [u8; {name}.size()] = {name}.descriptor();", name = name).unwrap();
+
+    Ok(out)
+}
+
+/// Generate the same items as [`capabilities`] from raw capability data and descriptor sets
+///
+/// See [`owned::Capabilities::from_data`] for requirements on the data.
+pub fn capabilities_from_bytes(data: &[u8], descriptor_sets: &[&[u8]]) -> Result<String, FromBytesError> {
+    Ok(capabilities(&owned::Capabilities::from_data(data, descriptor_sets)?)?)
+}
+
+/// Generate the same items as [`descriptor_set`] from raw descriptor set data
+///
+/// The generated descriptor set encodes to the same data, except for reserved fields which are
+/// always zero.
+pub fn descriptor_set_from_bytes(name: &str, data: &[u8]) -> Result<String, FromBytesError> {
+    Ok(descriptor_set(name, &owned::DescriptorSet::from_bytes(data)?)?)
+}
+
+fn features(out: &mut String, level: usize, features: &[owned::FeatureDescriptor]) {
+    let indent = "    ".repeat(level);
+    if features.is_empty() {
+        writeln!(out, "{}features: &[],", indent).unwrap();
+        return;
+    }
+    writeln!(out, "{}features: &[", indent).unwrap();
+    for feature in features {
+        write!(out, "{}    {}::os_20::FeatureDescriptor::", indent, CRATE).unwrap();
+        match feature {
+            owned::FeatureDescriptor::CompatibleId { id, sub_id } => {
+                writeln!(out, "CompatibleId {{ id: {}, sub_id: {} }},", byte_string(id), byte_string(sub_id)).unwrap();
+            },
+            owned::FeatureDescriptor::RegistryProperty { data_type, name, data } => {
+                writeln!(out, "RegistryProperty {{").unwrap();
+                writeln!(out, "{}        data_type: {}::os_20::PropertyDataType::{:?},", indent, CRATE, data_type).unwrap();
+                writeln!(out, "{}        name: &{},", indent, property_name(name)).unwrap();
+                writeln!(out, "{}        data: &{},", indent, property_data(*data_type, data)).unwrap();
+                writeln!(out, "{}    }},", indent).unwrap();
+            },
+            owned::FeatureDescriptor::ResumeTime { recovery, signaling } => {
+                writeln!(out, "ResumeTime {{ recovery: {}, signaling: {} }},", recovery, signaling).unwrap();
+            },
+            owned::FeatureDescriptor::ModelId { id } => {
+                writeln!(out, "ModelId {{ id: &{} }},", byte_array(id)).unwrap();
+            },
+            owned::FeatureDescriptor::CcgpDevice => writeln!(out, "CcgpDevice,").unwrap(),
+            owned::FeatureDescriptor::VendorRevision { revision } => {
+                writeln!(out, "VendorRevision {{ revision: {} }},", revision).unwrap();
+            },
+        }
+    }
+    writeln!(out, "{}],", indent).unwrap();
+}
+
+/// Byte string literal, e.g. `b"WINUSB\0\0"`
+fn byte_string(bytes: &[u8]) -> String {
+    let escaped: String = bytes.iter()
+        .map(|&b| match b {
+            0 => String::from("\\0"),
+            _ => core::ascii::escape_default(b).to_string(),
+        })
+        .collect();
+    format!("b\"{}\"", escaped)
+}
+
+fn byte_array(bytes: &[u8]) -> String {
+    let bytes: Vec<_> = bytes.iter().map(|b| format!("0x{:02x}", b)).collect();
+    format!("[{}]", bytes.join(", "))
+}
+
+fn property_name(name: &[u16]) -> String {
+    match utf16_null_str(name) {
+        Some(text) => format!("{}::utf16_lit::utf16_null!({:?})", CRATE, text),
+        None => format!("{:?}", name),
+    }
+}
+
+fn property_data(data_type: PropertyDataType, data: &[u8]) -> String {
+    use PropertyDataType::*;
+    let strings = utf16_null_strings(data).unwrap_or_default();
+    let text = match (data_type, strings.as_slice()) {
+        (RegSz | RegExpandSz | RegLink, [text]) => Some(text.clone()),
+        // utf16_null! adds the last NULL terminator
+        (RegMutliSz, [texts @ .., last]) if last.is_empty() => {
+            Some(texts.iter().map(|s| format!("{}\0", s)).collect::<String>())
+        },
+        _ => None,
+    };
+    match text {
+        Some(text) => format!("{}::utf16_null_le_bytes!({:?})", CRATE, text),
+        None => byte_array(data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os_20::owned::{CapabilityInfo, ConfigurationSubset, DescriptorSet, FeatureDescriptor, FunctionSubset};
+
+    fn utf16_null(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain([0]).collect()
+    }
+
+    fn winusb_set() -> DescriptorSet {
+        DescriptorSet {
+            version: WindowsVersion::MINIMAL,
+            features: vec![],
+            configurations: vec![ConfigurationSubset {
+                configuration: 0,
+                features: vec![],
+                functions: vec![FunctionSubset {
+                    first_interface: 3,
+                    features: vec![
+                        FeatureDescriptor::CompatibleId { id: *b"WINUSB\0\0", sub_id: [0; 8] },
+                        FeatureDescriptor::RegistryProperty {
+                            data_type: PropertyDataType::RegMutliSz,
+                            name: utf16_null("DeviceInterfaceGUIDs"),
+                            data: utf16_null("{6b09aac4-333f-4467-9e23-f88b9e9d95f7}\0")
+                                .into_iter()
+                                .flat_map(u16::to_le_bytes)
+                                .collect(),
+                        },
+                    ],
+                }],
+            }],
+        }
+    }
+
+    #[test]
+    fn descriptor_set_code() {
+        let code = descriptor_set("SET", &winusb_set()).unwrap();
+        assert_eq!(code, r#"/// MS OS 2.0 descriptor set
+pub const SET: ::usbd_microsoft_os::os_20::DescriptorSet = ::usbd_microsoft_os::os_20::DescriptorSet {
+    version: ::usbd_microsoft_os::WindowsVersion::WinBlue,
+    features: &[],
+    configurations: &[
+        ::usbd_microsoft_os::os_20::ConfigurationSubset {
+            configuration: 0,
+            features: &[],
+            functions: &[
+                ::usbd_microsoft_os::os_20::FunctionSubset {
+                    first_interface: 3,
+                    features: &[
+                        ::usbd_microsoft_os::os_20::FeatureDescriptor::CompatibleId { id: b"WINUSB\0\0", sub_id: b"\0\0\0\0\0\0\0\0" },
+                        ::usbd_microsoft_os::os_20::FeatureDescriptor::RegistryProperty {
+                            data_type: ::usbd_microsoft_os::os_20::PropertyDataType::RegMutliSz,
+                            name: &::usbd_microsoft_os::utf16_lit::utf16_null!("DeviceInterfaceGUIDs"),
+                            data: &::usbd_microsoft_os::utf16_null_le_bytes!("{6b09aac4-333f-4467-9e23-f88b9e9d95f7}\0"),
+                        },
+                    ],
+                },
+            ],
+        },
+    ],
+};
+
+/// Raw data of [`SET`]
+pub const SET_BYTES: [u8; SET.size()] = SET.descriptor();
+"#);
+    }
+
+    #[test]
+    fn raw_values_code() {
+        assert_eq!(byte_string(b"A\x01\"\0"), r#"b"A\x01\"\0""#);
+        assert_eq!(property_name(&[0x41, 0x42]), "[65, 66]");
+        assert_eq!(property_data(PropertyDataType::RegDwordLittleEndian, &[1, 0, 0, 0]), "[0x01, 0x00, 0x00, 0x00]");
+        assert_eq!(property_data(PropertyDataType::RegSz, &[0x41, 0, 0, 0]),
+            "::usbd_microsoft_os::utf16_null_le_bytes!(\"A\")");
+        // Missing NULL terminator
+        assert_eq!(property_data(PropertyDataType::RegSz, &[0x41, 0]), "[0x41, 0x00]");
+    }
+
+    #[test]
+    fn capabilities_code() {
+        let caps = owned::Capabilities {
+            infos: vec![
+                CapabilityInfo { descriptors: winusb_set(), alt_enum_cmd: 0 },
+                CapabilityInfo { descriptors: winusb_set(), alt_enum_cmd: 1 },
+            ],
+        };
+        let code = capabilities(&caps).unwrap();
+        assert!(code.contains("pub const DESCRIPTOR_SET_0: "));
+        assert!(code.contains("pub const DESCRIPTOR_SET_1_BYTES: "));
+        assert!(code.contains("descriptors: &DESCRIPTOR_SET_1,\n            alt_enum_cmd: 1,"));
+        assert!(code.contains("pub const DESCRIPTOR_SETS: &[&[u8]] = &[&DESCRIPTOR_SET_0_BYTES, &DESCRIPTOR_SET_1_BYTES];"));
+
+        let mut set = winusb_set();
+        set.version = WindowsVersion::Win8;
+        assert_eq!(descriptor_set("SET", &set), Err(Error::InvalidVersion));
+        set.version = WindowsVersion::Other(0x0A00_000F);
+        assert!(descriptor_set("SET", &set).unwrap()
+            .contains("version: ::usbd_microsoft_os::WindowsVersion::Other(0x0a00000f),"));
+    }
+
+    #[test]
+    fn code_from_bytes() {
+        let set = winusb_set();
+        let data = set.to_bytes().unwrap();
+        assert_eq!(descriptor_set_from_bytes("SET", &data).unwrap(), descriptor_set("SET", &set).unwrap());
+        assert_eq!(descriptor_set_from_bytes("SET", &data[..20]),
+            Err(FromBytesError::Decode(DecodeError::UnexpectedEnd { offset: 0 })));
+
+        let caps = owned::Capabilities { infos: vec![CapabilityInfo { descriptors: set, alt_enum_cmd: 0 }] };
+        let caps_data = caps.data_to_vec().unwrap();
+        assert_eq!(capabilities_from_bytes(&caps_data, &[&data]).unwrap(), capabilities(&caps).unwrap());
+
+        // Version 0x06020000 (Win8) can be decoded but not generated
+        let mut data = data;
+        data[6] = 0x02;
+        assert_eq!(descriptor_set_from_bytes("SET", &data), Err(FromBytesError::Descriptor(Error::InvalidVersion)));
+    }
+}
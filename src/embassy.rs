@@ -0,0 +1,182 @@
+use embassy_usb::control::{InResponse, OutResponse, Recipient, Request, RequestType};
+use embassy_usb::driver::Driver;
+use embassy_usb::{Handler, InterfaceAltBuilder};
+
+use crate::os_20::{Capabilities, DescriptorIndex};
+
+/// [`embassy_usb::Handler`] responsible for handling MS OS descriptor requests
+///
+/// This is the embassy-usb counterpart of [`crate::MsOsUsbClass`] and it uses the same statically
+/// generated descriptor data. Use [`crate::os_20::DescriptorSet::descriptor`] and
+/// [`crate::os_20::Capabilities::descriptor_data`] const functions to generate the descriptors.
+/// Descriptor sets are sent directly from the `'static` data, so their size is not limited by
+/// the control buffer passed to `embassy_usb::Builder`.
+///
+/// embassy-usb only allows adding BOS capabilities from an interface alternate setting, so the
+/// handler has to be registered in two steps. The capability applies to the whole device, so use
+/// an alternate setting of an interface the device already has, e.g. the WinUSB interface that
+/// the descriptor set targets:
+///
+/// ```ignore
+/// // Vendor-specific interface bound to WinUSB by the descriptor set
+/// let mut function = builder.function(0xff, 0, 0);
+/// let mut interface = function.interface();
+/// let mut alt = interface.alt_setting(0xff, 0, 0, None);
+/// let ep_out = alt.endpoint_bulk_out(None, 64);
+/// let ep_in = alt.endpoint_bulk_in(None, 64);
+/// MS_OS_HANDLER.add_bos_capability(&mut alt);
+/// drop(function);
+/// builder.handler(&mut MS_OS_HANDLER);
+/// ```
+///
+/// Do not use the builder's own `msos_descriptor`, as embassy-usb would then answer the requests
+/// before this handler gets called.
+pub struct MsOsHandler {
+    /// Capabilities data obtained from [`crate::os_20::Capabilities::descriptor_data`]
+    pub os_20_capabilities_data: &'static [u8],
+    /// Data for each descriptor obtained from [`crate::os_20::DescriptorSet::descriptor`]
+    pub os_20_descriptor_sets: &'static [&'static [u8]],
+    requested_set: Option<usize>,
+}
+
+impl MsOsHandler {
+    /// Create new handler from capabilities data and descriptor sets
+    ///
+    /// Descriptor sets must be in the same order as [`crate::os_20::Capabilities::infos`].
+    pub const fn new(
+        os_20_capabilities_data: &'static [u8],
+        os_20_descriptor_sets: &'static [&'static [u8]],
+    ) -> Self {
+        Self {
+            os_20_capabilities_data,
+            os_20_descriptor_sets,
+            requested_set: None,
+        }
+    }
+
+    /// Add MS OS 2.0 platform capability to the device BOS descriptor
+    ///
+    /// BOS capabilities apply to the whole device, so any alternate setting can be used.
+    pub fn add_bos_capability<'d, D: Driver<'d>>(&self, alt: &mut InterfaceAltBuilder<'_, 'd, D>) {
+        trace!("MS OS: writing BOS platform capability ({} bytes)", self.os_20_capabilities_data.len());
+        alt.bos_capability(Capabilities::CAPABILITY_TYPE, self.os_20_capabilities_data);
+    }
+
+    /// Index of the descriptor set most recently sent to the host
+    ///
+    /// See [`crate::MsOsUsbClass::host_requested_set`].
+    pub fn host_requested_set(&self) -> Option<usize> {
+        self.requested_set
+    }
+}
+
+impl Handler for MsOsHandler {
+    fn reset(&mut self) {
+        self.requested_set = None;
+    }
+
+    fn control_in<'a>(&'a mut self, req: Request, _buf: &'a mut [u8]) -> Option<InResponse<'a>> {
+        // MS OS 2.0 get descriptors request
+        if req.request_type != RequestType::Vendor
+            || req.recipient != Recipient::Device
+            || req.index != DescriptorIndex::Descriptor as u16
+        {
+            return None;
+        }
+
+        debug!("MS OS: descriptor set request: vendor_code={}, wIndex={}, wLength={}",
+            req.request, req.index, req.length);
+
+        let descriptor_set = Capabilities::vendor_code_to_descriptor_set(req.request)
+            .and_then(|i| Some((i, *self.os_20_descriptor_sets.get(i)?)));
+
+        // Other vendor requests with the same wIndex may be handled by different handlers
+        let (i, set) = descriptor_set?;
+        debug!("MS OS: accepting with descriptor set {} ({} bytes)", i, set.len());
+        self.requested_set = Some(i);
+        Some(InResponse::Accepted(set))
+    }
+
+    fn control_out(&mut self, req: Request, _data: &[u8]) -> Option<OutResponse> {
+        // MS OS 2.0 set alternate enumeration command
+        if req.request_type != RequestType::Vendor
+            || req.recipient != Recipient::Device
+            || req.index != DescriptorIndex::SetAltEnumeration as u16
+        {
+            return None;
+        }
+
+        let alt_enum_code = req.value.to_le_bytes()[1];
+        debug!("MS OS: set alternate enumeration request: vendor_code={}, bAltEnumCode={}",
+            req.request, alt_enum_code);
+        // FIXME: not supported yet
+        warn!("MS OS: rejecting set alternate enumeration (not supported)");
+        Some(OutResponse::Rejected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os_20::{CapabilityInfo, DescriptorSet, FeatureDescriptor};
+    use crate::WindowsVersion;
+
+    const SET: DescriptorSet = DescriptorSet {
+        version: WindowsVersion::MINIMAL,
+        features: &[FeatureDescriptor::CcgpDevice],
+        configurations: &[],
+    };
+    const SET_BYTES: [u8; SET.size()] = SET.descriptor();
+    const CAPABILITIES: Capabilities = Capabilities {
+        infos: &[CapabilityInfo { descriptors: &SET, alt_enum_cmd: 0 }],
+    };
+    const CAPABILITIES_DATA: [u8; CAPABILITIES.data_len()] = CAPABILITIES.descriptor_data();
+
+    /// MS OS 2.0 descriptor request for given vendor code
+    fn descriptor_request(vendor_code: u8) -> Request {
+        let [index_lo, index_hi] = (DescriptorIndex::Descriptor as u16).to_le_bytes();
+        Request::parse(&[0xc0, vendor_code, 0, 0, index_lo, index_hi, 0xff, 0x00])
+    }
+
+    #[test]
+    fn host_requested_set() {
+        let mut handler = MsOsHandler::new(&CAPABILITIES_DATA, &[&SET_BYTES]);
+        assert_eq!(handler.host_requested_set(), None);
+
+        let request = descriptor_request(Capabilities::vendor_code_descriptor_set(0));
+        assert!(handler.control_in(request, &mut []).is_some());
+        assert_eq!(handler.host_requested_set(), Some(0));
+
+        handler.reset();
+        assert_eq!(handler.host_requested_set(), None);
+    }
+
+    #[test]
+    fn control_in() {
+        const OTHER_SET_BYTES: [u8; SET.size()] = DescriptorSet { version: WindowsVersion::Win10, ..SET }.descriptor();
+        let mut handler = MsOsHandler::new(&CAPABILITIES_DATA, &[&SET_BYTES, &OTHER_SET_BYTES]);
+
+        let request = descriptor_request(Capabilities::vendor_code_descriptor_set(1));
+        assert_eq!(handler.control_in(request, &mut []), Some(InResponse::Accepted(&OTHER_SET_BYTES[..])));
+        assert_eq!(handler.host_requested_set(), Some(1));
+        let request = descriptor_request(Capabilities::vendor_code_descriptor_set(0));
+        assert_eq!(handler.control_in(request, &mut []), Some(InResponse::Accepted(&SET_BYTES[..])));
+        assert_eq!(handler.host_requested_set(), Some(0));
+    }
+
+    #[test]
+    fn control_in_ignored() {
+        let mut handler = MsOsHandler::new(&CAPABILITIES_DATA, &[&SET_BYTES]);
+        let valid = descriptor_request(Capabilities::vendor_code_descriptor_set(0));
+
+        // Left for other handlers: unknown vendor code, other wIndex, request type or recipient
+        let unknown = descriptor_request(Capabilities::vendor_code_descriptor_set(1));
+        let set_alt_enumeration = Request { index: DescriptorIndex::SetAltEnumeration as u16, ..valid };
+        let standard = Request { request_type: RequestType::Standard, ..valid };
+        let interface = Request { recipient: Recipient::Interface, ..valid };
+        for request in [unknown, set_alt_enumeration, standard, interface] {
+            assert_eq!(handler.control_in(request, &mut []), None);
+        }
+        assert_eq!(handler.host_requested_set(), None);
+    }
+}
@@ -0,0 +1,204 @@
+//! Generate WinUSB INF files equivalent to MS OS 2.0 descriptors
+//!
+//! The INF installs WinUSB for each device or function with `WINUSB` compatible ID and adds
+//! its registry properties in the `[<Install>.HW]` section, so it can be used as a fallback on
+//! systems that ignore MS OS descriptors. Features that cannot be expressed in an INF (e.g.
+//! [`FeatureDescriptor::ResumeTime`](crate::os_20::FeatureDescriptor::ResumeTime)) are listed
+//! as comments.
+
+use core::fmt::Write;
+use std::string::String;
+use std::vec::Vec;
+
+use crate::os_20::owned;
+use crate::os_20::PropertyDataType;
+use crate::registry::{self, Error, Value};
+
+/// Device information used in the INF
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InfOptions<'a> {
+    /// USB Vendor ID
+    pub vid: u16,
+    /// USB Product ID
+    pub pid: u16,
+    /// Manufacturer name
+    pub manufacturer: &'a str,
+    /// Device name shown in Device Manager
+    pub device_name: &'a str,
+    /// DriverVer value, date and version, e.g. `01/01/2024,1.0.0.0`
+    pub driver_ver: &'a str,
+}
+
+// Flags for registry value types in AddReg entries
+const FLG_ADDREG_BINVALUETYPE: u32 = 0x0000_0001;
+const FLG_ADDREG_TYPE_SZ: u32 = 0x0000_0000;
+const FLG_ADDREG_TYPE_MULTI_SZ: u32 = 0x0001_0000;
+const FLG_ADDREG_TYPE_EXPAND_SZ: u32 = 0x0002_0000;
+const FLG_ADDREG_TYPE_DWORD: u32 = 0x0001_0001;
+
+/// Generate INF for all WinUSB devices/functions of a descriptor set
+///
+/// Returns [`Error::NoWinUsb`] if there is no `WINUSB` compatible ID in the descriptor set and
+/// [`Error::UnsupportedInfValue`] if a string property has data that is not valid for its type.
+pub fn generate(set: &owned::DescriptorSet, options: &InfOptions) -> Result<String, Error> {
+    let targets: Vec<_> = registry::targets(set)?
+        .into_iter()
+        .filter(registry::Target::is_winusb)
+        .collect();
+    if targets.is_empty() {
+        return Err(Error::NoWinUsb);
+    }
+
+    let mut out = String::new();
+    writeln!(out, "; Generated from MS OS 2.0 descriptor set by usbd-microsoft-os").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "[Version]").unwrap();
+    writeln!(out, "Signature   = \"$Windows NT$\"").unwrap();
+    writeln!(out, "Class       = USBDevice").unwrap();
+    writeln!(out, "ClassGuid   = {{88BAE032-5A81-49f0-BC3D-A4FF138216D6}}").unwrap();
+    writeln!(out, "Provider    = %ManufacturerName%").unwrap();
+    writeln!(out, "DriverVer   = {}", options.driver_ver).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "[Manufacturer]").unwrap();
+    writeln!(out, "%ManufacturerName% = Standard,NTamd64,NTx86,NTarm64").unwrap();
+
+    for arch in ["NTamd64", "NTx86", "NTarm64"] {
+        writeln!(out).unwrap();
+        writeln!(out, "[Standard.{}]", arch).unwrap();
+        for (i, target) in targets.iter().enumerate() {
+            writeln!(out, "%DeviceName% = Install{}, {}", i, target.hardware_id(options.vid, options.pid)).unwrap();
+        }
+    }
+
+    for (i, target) in targets.iter().enumerate() {
+        writeln!(out).unwrap();
+        writeln!(out, "[Install{}]", i).unwrap();
+        writeln!(out, "Include = winusb.inf").unwrap();
+        writeln!(out, "Needs   = WINUSB.NT").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "[Install{}.Services]", i).unwrap();
+        writeln!(out, "Include = winusb.inf").unwrap();
+        writeln!(out, "Needs   = WINUSB.NT.Services").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "[Install{}.HW]", i).unwrap();
+        writeln!(out, "AddReg = Install{}.AddReg", i).unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "[Install{}.AddReg]", i).unwrap();
+        for property in &target.properties {
+            let value = add_reg_value(&property.value)
+                .ok_or_else(|| Error::UnsupportedInfValue(property.name.clone()))?;
+            writeln!(out, "HKR,,{},{}", quote(&property.name), value).unwrap();
+        }
+        for feature in &target.other_features {
+            writeln!(out, "; Not supported in INF: {:?}", feature).unwrap();
+        }
+    }
+
+    writeln!(out).unwrap();
+    writeln!(out, "[Strings]").unwrap();
+    writeln!(out, "ManufacturerName = {}", quote(options.manufacturer)).unwrap();
+    writeln!(out, "DeviceName = {}", quote(options.device_name)).unwrap();
+
+    Ok(out)
+}
+
+/// Quote INF string, escaping quotes and `%` substitutions
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\"\"").replace('%', "%%"))
+}
+
+/// Get `flags,value` part of AddReg entry
+///
+/// Returns `None` for values that cannot be expressed in an INF: raw data of string types (the
+/// high word of the flags would collide with `FLG_ADDREG_TYPE_*` flags) and empty strings in
+/// multi-string values (they would end the list).
+fn add_reg_value(value: &Value) -> Option<String> {
+    let value = match value {
+        Value::Sz(s) => format!("0x{:08x},{}", FLG_ADDREG_TYPE_SZ, quote(s)),
+        Value::ExpandSz(s) => format!("0x{:08x},{}", FLG_ADDREG_TYPE_EXPAND_SZ, quote(s)),
+        Value::MultiSz(strings) if strings.iter().any(String::is_empty) => return None,
+        Value::MultiSz(strings) => {
+            let strings: Vec<_> = strings.iter().map(|s| quote(s)).collect();
+            format!("0x{:08x},{}", FLG_ADDREG_TYPE_MULTI_SZ, strings.join(","))
+        },
+        Value::Dword(value) => format!("0x{:08x},0x{:08x}", FLG_ADDREG_TYPE_DWORD, value),
+        Value::Raw(PropertyDataType::RegSz | PropertyDataType::RegExpandSz | PropertyDataType::RegMutliSz, _) => {
+            return None
+        },
+        Value::Raw(data_type, data) => {
+            let flags = match data_type {
+                PropertyDataType::RegBinary => FLG_ADDREG_BINVALUETYPE,
+                // Binary value with registry type in the high word
+                _ => ((*data_type as u32) << 16) | FLG_ADDREG_BINVALUETYPE,
+            };
+            let bytes: Vec<_> = data.iter().map(|b| format!("{:02x}", b)).collect();
+            match bytes.is_empty() {
+                true => format!("0x{:08x}", flags),
+                false => format!("0x{:08x},{}", flags, bytes.join(",")),
+            }
+        },
+    };
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os_20::tests::EXAMPLE_SET;
+
+    const OPTIONS: InfOptions = InfOptions {
+        vid: 0x1209,
+        pid: 0x0001,
+        manufacturer: "ACME",
+        device_name: "Gadget \"100%\"",
+        driver_ver: "01/01/2024,1.0.0.0",
+    };
+
+    #[test]
+    fn example_inf() {
+        let inf = generate(&owned::DescriptorSet::from(&EXAMPLE_SET), &OPTIONS).unwrap();
+        assert!(inf.contains("[Standard.NTamd64]\n%DeviceName% = Install0, USB\\VID_1209&PID_0001&MI_01\n"));
+        assert!(inf.contains("[Install0.HW]\nAddReg = Install0.AddReg\n"));
+        assert!(inf.contains(
+            "[Install0.AddReg]\nHKR,,\"DeviceInterfaceGUIDs\",0x00010000,\"{897d7b90-5aae-43e5-9c36-aa0f2fdbafc9}\"\n"));
+        assert!(inf.contains("DeviceName = \"Gadget \"\"100%%\"\"\"\n"));
+    }
+
+    #[test]
+    fn add_reg_values() {
+        assert_eq!(add_reg_value(&Value::Sz("a".into())).unwrap(), "0x00000000,\"a\"");
+        assert_eq!(add_reg_value(&Value::ExpandSz("%a%".into())).unwrap(), "0x00020000,\"%%a%%\"");
+        assert_eq!(add_reg_value(&Value::MultiSz(vec!["a".into(), "b".into()])).unwrap(), "0x00010000,\"a\",\"b\"");
+        assert_eq!(add_reg_value(&Value::Dword(5)).unwrap(), "0x00010001,0x00000005");
+        assert_eq!(add_reg_value(&Value::Raw(PropertyDataType::RegBinary, vec![1, 0xab])).unwrap(), "0x00000001,01,ab");
+        assert_eq!(add_reg_value(&Value::Raw(PropertyDataType::RegDwordBigEndian, vec![0, 0, 0, 1])).unwrap(),
+            "0x00050001,00,00,00,01");
+        assert_eq!(add_reg_value(&Value::Raw(PropertyDataType::RegLink, vec![])).unwrap(), "0x00060001");
+    }
+
+    #[test]
+    fn unsupported_add_reg_values() {
+        // Raw data of string types would get flags of other types, e.g. 0x00010001 is REG_DWORD
+        assert_eq!(add_reg_value(&Value::Raw(PropertyDataType::RegSz, vec![0x41, 0])), None);
+        assert_eq!(add_reg_value(&Value::Raw(PropertyDataType::RegExpandSz, vec![0x41, 0])), None);
+        assert_eq!(add_reg_value(&Value::Raw(PropertyDataType::RegMutliSz, vec![0x41, 0])), None);
+        assert_eq!(add_reg_value(&Value::MultiSz(vec!["a".into(), "".into(), "b".into()])), None);
+    }
+
+    #[test]
+    fn unsupported_value() {
+        let mut set = owned::DescriptorSet::from(&EXAMPLE_SET);
+        let owned::FeatureDescriptor::RegistryProperty { data, .. } =
+            &mut set.configurations[0].functions[0].features[1] else { panic!() };
+        *data = owned::utf16_null_bytes(["{897d7b90-5aae-43e5-9c36-aa0f2fdbafc9}", "", "x", ""]);
+        assert_eq!(generate(&set, &OPTIONS),
+            Err(Error::UnsupportedInfValue("DeviceInterfaceGUIDs".into())));
+    }
+
+    #[test]
+    fn no_winusb() {
+        let mut set = owned::DescriptorSet::from(&EXAMPLE_SET);
+        set.configurations[0].functions[0].features.remove(0);
+        assert_eq!(generate(&set, &OPTIONS), Err(Error::NoWinUsb));
+    }
+}
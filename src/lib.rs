@@ -9,7 +9,9 @@
 //! describe the descriptors, and `const fn` methods that generate raw descriptor data, e.g. for WinUSB:
 //!
 //! ```rust
-//! use usbd_microsoft_os::{os_20, MsOsUsbClass, WindowsVersion, utf16_lit, utf16_null_le_bytes};
+//! use usbd_microsoft_os::{os_20, WindowsVersion, utf16_lit, utf16_null_le_bytes};
+//! # #[cfg(any(feature = "usb-device-02", feature = "usb-device-03"))]
+//! use usbd_microsoft_os::MsOsUsbClass;
 //!
 //! const DESCRIPTOR_SET: os_20::DescriptorSet = os_20::DescriptorSet {
 //!     version: WindowsVersion::MINIMAL,
@@ -50,35 +52,90 @@
 //! const DESCRIPTOR_SET_BYTES: [u8; DESCRIPTOR_SET.size()] = DESCRIPTOR_SET.descriptor();
 //! const CAPABILITIES_BYTES: [u8; CAPABILITIES.data_len()] = CAPABILITIES.descriptor_data();
 //!
+//! # #[cfg(any(feature = "usb-device-02", feature = "usb-device-03"))]
 //! pub const fn class() -> MsOsUsbClass {
-//!     MsOsUsbClass {
-//!         os_20_capabilities_data: &CAPABILITIES_BYTES,
-//!         os_20_descriptor_sets: &[&DESCRIPTOR_SET_BYTES],
-//!     }
+//!     MsOsUsbClass::new(&CAPABILITIES_BYTES, &[&DESCRIPTOR_SET_BYTES])
 //! }
 //! ```
 //!
 //! Check test cases to see more examples from the specification.
+//!
+//! ## Cargo features
+//!
+//! * `usb-device-02` (default) - use [usb-device](https://crates.io/crates/usb-device) 0.2 for `MsOsUsbClass`
+//! * `usb-device-03` - use usb-device 0.3 for `MsOsUsbClass` (disable default features to use it)
+//! * `control-buffer-256` - enable usb-device's `control-buffer-256` and take it into account in `class::assert_fits_control_buffer`
+//! * `embassy-usb` - provide `embassy::MsOsHandler` for [embassy-usb](https://crates.io/crates/embassy-usb)
+//! * `defmt` - implement `defmt::Format` for descriptor structures and log class requests using `defmt`
+//! * `log` - log class requests using `log` (mutually exclusive with `defmt`)
+//! * `alloc` - owned descriptor structures in `os_20::owned` with `DescriptorSetBuilder`, encoding and decoding them to/from raw data, validation in `lint`
+//! * `std` - host-side generators: Rust source code in `codegen`, C headers in `c_header`, WinUSB INF files in `inf`, `.reg` files in `reg`; usbmon capture analysis in `usbmon` (implies `alloc`)
+//! * `serde` - implement `Serialize`/`Deserialize` for owned descriptor structures, `PropertyDataType` and `WindowsVersion` (implies `alloc`)
+//! * `build` - `build` module for generating descriptors in `build.rs` from TOML/JSON definitions
+//! * `cli` - `msos` command-line tool for encoding, decoding, linting descriptors and generating host files
 
 #![no_std]
 #![deny(missing_docs)]
 
 // Include std when running tests
-#[cfg(test)]
+#[cfg(any(test, feature = "std"))]
 #[macro_use]
 extern crate std;
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(all(feature = "usb-device-02", feature = "usb-device-03"))]
+compile_error!("Features `usb-device-02` and `usb-device-03` are mutually exclusive.");
+
+#[cfg(all(feature = "usb-device-02", not(feature = "usb-device-03")))]
+extern crate usb_device_02 as usb_device;
+#[cfg(feature = "usb-device-03")]
+extern crate usb_device_03 as usb_device;
+
+// This must go first so that the macros are visible in other modules
+#[macro_use]
+mod fmt;
 
 /// Re-export of utf16_lit for constructing utf16 literals in compile time
 pub extern crate utf16_lit;
 
+/// Build script helpers generating descriptors from TOML/JSON definitions
+#[cfg(feature = "build")]
+pub mod build;
+/// C header generation for descriptor data
+#[cfg(feature = "std")]
+pub mod c_header;
 /// USB class definition
+#[cfg(any(feature = "usb-device-02", feature = "usb-device-03"))]
 pub mod class;
+/// Rust source code generation for descriptor structures
+#[cfg(feature = "std")]
+pub mod codegen;
+/// embassy-usb handler definition
+#[cfg(feature = "embassy-usb")]
+pub mod embassy;
+/// WinUSB INF generation
+#[cfg(feature = "std")]
+pub mod inf;
+/// Validation of descriptor sets
+#[cfg(feature = "alloc")]
+pub mod lint;
 /// Microsoft OS 2.0 Descriptors
 pub mod os_20;
+/// Registry file generation
+#[cfg(feature = "std")]
+pub mod reg;
+/// Registry values resulting from descriptors
+#[cfg(feature = "std")]
+pub mod registry;
+/// Analysis of MS OS descriptor traffic in usbmon captures
+#[cfg(feature = "std")]
+pub mod usbmon;
 /// Windows NTDDI version definitions
 pub mod windows_version;
 
-pub use class::MsOsUsbClass;
+#[cfg(any(feature = "usb-device-02", feature = "usb-device-03"))]
+pub use class::{DynamicMsOsUsbClass, MsOsUsbClass};
 pub use windows_version::WindowsVersion;
 
 /// Generate UTF-16 string using [`utf16_lit::utf16_null`] and get it as little-endian bytes array
@@ -0,0 +1,361 @@
+//! Validation of descriptor sets against the MS OS 2.0 specification
+//!
+//! Encoding only fails for invalid Windows version, while many other mistakes (e.g. invalid
+//! registry data, features at wrong level) result in descriptors silently ignored by Windows.
+//! [`lint`] reports such problems for an owned descriptor set.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::os_20::owned::{DescriptorSet, FeatureDescriptor};
+use crate::os_20::{FeaturePath, PropertyDataType};
+
+/// Severity of a lint
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Descriptor is allowed but likely not what was intended
+    Warning,
+    /// Descriptor violates the specification
+    Error,
+}
+
+/// Location of a lint within a descriptor set
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Location {
+    /// The whole descriptor set
+    Set,
+    /// Configuration subset with given index
+    Configuration {
+        /// Index of the configuration subset
+        configuration: usize,
+    },
+    /// Function subset with given indices
+    Function {
+        /// Index of the configuration subset
+        configuration: usize,
+        /// Index of the function subset
+        function: usize,
+    },
+    /// Feature descriptor
+    Feature(FeaturePath),
+}
+
+impl core::fmt::Display for Location {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::Set => f.write_str("descriptor set"),
+            Self::Configuration { configuration } => write!(f, "configuration {}", configuration),
+            Self::Function { configuration, function } => {
+                write!(f, "configuration {} function {}", configuration, function)
+            },
+            Self::Feature(FeaturePath::Device { feature }) => write!(f, "device feature {}", feature),
+            Self::Feature(FeaturePath::Configuration { configuration, feature }) => {
+                write!(f, "configuration {} feature {}", configuration, feature)
+            },
+            Self::Feature(FeaturePath::Function { configuration, function, feature }) => {
+                write!(f, "configuration {} function {} feature {}", configuration, function, feature)
+            },
+        }
+    }
+}
+
+/// Problem found in a descriptor set
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lint {
+    /// Severity
+    pub severity: Severity,
+    /// Where the problem is
+    pub location: Location,
+    /// Description of the problem
+    pub message: String,
+}
+
+impl core::fmt::Display for Lint {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}: {}", severity, self.location, self.message)
+    }
+}
+
+/// Level at which a feature descriptor is placed
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Level {
+    Device,
+    Configuration,
+    Function,
+}
+
+struct Linter {
+    lints: Vec<Lint>,
+}
+
+impl Linter {
+    fn push(&mut self, severity: Severity, location: Location, message: String) {
+        self.lints.push(Lint { severity, location, message });
+    }
+
+    fn features(&mut self, level: Level, features: &[FeatureDescriptor], path: impl Fn(usize) -> FeaturePath) {
+        for (i, feature) in features.iter().enumerate() {
+            let location = Location::Feature(path(i));
+            self.feature(level, feature, location);
+
+            let duplicate = features[..i].iter().any(|other| match (feature, other) {
+                (
+                    FeatureDescriptor::RegistryProperty { name, .. },
+                    FeatureDescriptor::RegistryProperty { name: other, .. },
+                ) => name == other,
+                _ => core::mem::discriminant(feature) == core::mem::discriminant(other),
+            });
+            if duplicate {
+                self.push(Severity::Error, location, String::from("duplicate feature descriptor"));
+            }
+        }
+    }
+
+    fn feature(&mut self, level: Level, feature: &FeatureDescriptor, location: Location) {
+        let device_only = |name: &str| format!("{} descriptor is only allowed at device level", name);
+        match feature {
+            FeatureDescriptor::CompatibleId { id, sub_id } => {
+                if level == Level::Configuration {
+                    self.push(Severity::Error, location,
+                        String::from("compatible ID descriptor is not allowed in configuration subset"));
+                }
+                for (name, id) in [("compatible ID", id), ("sub-compatible ID", sub_id)] {
+                    let len = id.iter().position(|&b| b == 0).unwrap_or(id.len());
+                    let valid = id[..len].iter().all(|&b| b.is_ascii_uppercase() || b.is_ascii_digit() || b == b'_')
+                        && id[len..].iter().all(|&b| b == 0);
+                    if !valid {
+                        self.push(Severity::Error, location, format!(
+                            "{} must consist of uppercase letters, digits and underscores padded with NULs", name));
+                    }
+                }
+            },
+            FeatureDescriptor::RegistryProperty { data_type, name, data } => {
+                self.registry_property(*data_type, name, data, location);
+            },
+            FeatureDescriptor::ResumeTime { recovery, signaling } => {
+                if level != Level::Device {
+                    self.push(Severity::Error, location, device_only("minimum resume time"));
+                }
+                if *recovery > 10 {
+                    self.push(Severity::Error, location, format!("resume recovery time {} ms exceeds 10 ms", recovery));
+                }
+                if !(1..=20).contains(signaling) {
+                    self.push(Severity::Error, location, format!("resume signaling time {} ms not in range 1..=20 ms", signaling));
+                }
+            },
+            FeatureDescriptor::ModelId { id } => {
+                if level != Level::Device {
+                    self.push(Severity::Error, location, device_only("model ID"));
+                }
+                if id.iter().all(|&b| b == 0) {
+                    self.push(Severity::Warning, location, String::from("model ID is all zeros"));
+                }
+            },
+            FeatureDescriptor::CcgpDevice => {
+                if level != Level::Device {
+                    self.push(Severity::Error, location, device_only("CCGP device"));
+                }
+            },
+            FeatureDescriptor::VendorRevision { revision } => {
+                if *revision == 0 {
+                    self.push(Severity::Error, location, String::from("vendor revision must be at least 1"));
+                }
+            },
+        }
+    }
+
+    fn registry_property(&mut self, data_type: PropertyDataType, name: &[u16], data: &[u8], location: Location) {
+        use PropertyDataType::*;
+
+        let name = match name.split_last() {
+            Some((0, text)) if !text.is_empty() && !text.contains(&0) => String::from_utf16(text).ok(),
+            _ => None,
+        };
+        let Some(name) = name else {
+            self.push(Severity::Error, location,
+                String::from("property name must be a non-empty NULL-terminated UTF-16 string"));
+            return;
+        };
+
+        let units: Option<Vec<u16>> = (data.len() % 2 == 0).then(|| {
+            data.chunks_exact(2).map(|b| u16::from_le_bytes([b[0], b[1]])).collect()
+        });
+        let problem = match (data_type, units.as_deref()) {
+            (RegSz | RegExpandSz | RegLink, Some([.., 0])) => None,
+            (RegSz | RegExpandSz | RegLink, _) => Some("string data must be NULL-terminated UTF-16"),
+            (RegMutliSz, Some([.., 0, 0])) => None,
+            (RegMutliSz, _) => Some("multi-string data must be UTF-16 terminated with two NULLs"),
+            (RegDwordLittleEndian | RegDwordBigEndian, _) if data.len() != 4 => Some("DWORD data must be 4 bytes long"),
+            _ => None,
+        };
+        if let Some(problem) = problem {
+            self.push(Severity::Error, location, format!("{}: {}", name, problem));
+        }
+
+        match (name.as_str(), data_type) {
+            ("DeviceInterfaceGUIDs", RegSz) => self.push(Severity::Warning, location,
+                String::from("DeviceInterfaceGUIDs should be RegMultiSz (or use DeviceInterfaceGUID for RegSz)")),
+            ("DeviceInterfaceGUID", RegMutliSz) => self.push(Severity::Warning, location,
+                String::from("DeviceInterfaceGUID should be RegSz (or use DeviceInterfaceGUIDs for RegMultiSz)")),
+            _ => {},
+        }
+
+        if name.starts_with("DeviceInterfaceGUID") && problem.is_none() {
+            let units = units.unwrap_or_default();
+            let strings = units.split(|&u| u == 0).filter(|s| !s.is_empty());
+            for guid in strings {
+                let guid = String::from_utf16_lossy(guid);
+                if !is_guid(&guid) {
+                    self.push(Severity::Error, location, format!("{}: invalid GUID string {:?}", name, guid));
+                }
+            }
+        }
+    }
+}
+
+/// Check GUID string in format `{xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx}`
+fn is_guid(s: &str) -> bool {
+    let Some(s) = s.strip_prefix('{').and_then(|s| s.strip_suffix('}')) else {
+        return false;
+    };
+    let groups: Vec<&str> = s.split('-').collect();
+    groups.iter().map(|g| g.len()).eq([8, 4, 4, 4, 12])
+        && groups.iter().all(|g| g.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+/// Check descriptor set for problems, sorted by location
+pub fn lint(set: &DescriptorSet) -> Vec<Lint> {
+    let mut linter = Linter { lints: Vec::new() };
+
+    if !set.version.is_allowed() {
+        linter.push(Severity::Error, Location::Set, format!("Windows version {:?} is lower than minimal WinBlue", set.version));
+    }
+    if set.check_len().is_err() {
+        linter.push(Severity::Error, Location::Set, format!("descriptor set size {} exceeds 65535", set.size()));
+    }
+
+    let all_features = || set.features.iter()
+        .chain(set.configurations.iter().flat_map(|c| &c.features))
+        .chain(set.configurations.iter().flat_map(|c| &c.functions).flat_map(|f| &f.features));
+    let has_property = all_features().any(|f| matches!(f, FeatureDescriptor::RegistryProperty { .. }));
+    let has_revision = all_features().any(|f| matches!(f, FeatureDescriptor::VendorRevision { .. }));
+    if has_property && !has_revision {
+        linter.push(Severity::Warning, Location::Set, String::from(
+            "registry properties without vendor revision, Windows will not update cached values on changes"));
+    }
+
+    linter.features(Level::Device, &set.features, |feature| FeaturePath::Device { feature });
+    for (c, config) in set.configurations.iter().enumerate() {
+        let location = Location::Configuration { configuration: c };
+        if set.configurations[..c].iter().any(|other| other.configuration == config.configuration) {
+            linter.push(Severity::Error, location, format!("duplicate configuration value {}", config.configuration));
+        }
+        if config.features.is_empty() && config.functions.is_empty() {
+            linter.push(Severity::Warning, location, String::from("empty configuration subset"));
+        }
+        linter.features(Level::Configuration, &config.features,
+            |feature| FeaturePath::Configuration { configuration: c, feature });
+
+        for (f, function) in config.functions.iter().enumerate() {
+            let location = Location::Function { configuration: c, function: f };
+            if config.functions[..f].iter().any(|other| other.first_interface == function.first_interface) {
+                linter.push(Severity::Error, location, format!("duplicate first interface {}", function.first_interface));
+            }
+            if function.features.is_empty() {
+                linter.push(Severity::Warning, location, String::from("empty function subset"));
+            }
+            linter.features(Level::Function, &function.features,
+                |feature| FeaturePath::Function { configuration: c, function: f, feature });
+        }
+    }
+
+    linter.lints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os_20::owned::{ConfigurationSubset, FunctionSubset};
+    use crate::os_20::tests::EXAMPLE_SET;
+    use crate::WindowsVersion;
+    use alloc::vec;
+
+    fn utf16(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    fn messages(set: &DescriptorSet) -> Vec<String> {
+        lint(set).iter().map(|lint| format!("{}", lint)).collect()
+    }
+
+    #[test]
+    fn example_set() {
+        let set = DescriptorSet::from(&EXAMPLE_SET);
+        assert_eq!(messages(&set), [
+            "warning: descriptor set: registry properties without vendor revision, Windows will not update cached values on changes",
+        ]);
+    }
+
+    #[test]
+    fn invalid_set() {
+        let set = DescriptorSet {
+            version: WindowsVersion::Win8,
+            features: vec![
+                FeatureDescriptor::ResumeTime { recovery: 11, signaling: 0 },
+                FeatureDescriptor::VendorRevision { revision: 0 },
+                FeatureDescriptor::VendorRevision { revision: 1 },
+            ],
+            configurations: vec![ConfigurationSubset {
+                configuration: 0,
+                features: vec![FeatureDescriptor::CcgpDevice],
+                functions: vec![FunctionSubset {
+                    first_interface: 0,
+                    features: vec![
+                        FeatureDescriptor::CompatibleId { id: *b"winusb\0\0", sub_id: *b"\0A\0\0\0\0\0\0" },
+                        FeatureDescriptor::RegistryProperty {
+                            data_type: PropertyDataType::RegSz,
+                            name: utf16("DeviceInterfaceGUIDs\0"),
+                            data: utf16("{1234}\0").into_iter().flat_map(u16::to_le_bytes).collect(),
+                        },
+                        FeatureDescriptor::RegistryProperty {
+                            data_type: PropertyDataType::RegDwordLittleEndian,
+                            name: utf16("Value"),
+                            data: vec![1, 0],
+                        },
+                    ],
+                }],
+            }],
+        };
+        assert_eq!(messages(&set), [
+            "error: descriptor set: Windows version Win8 is lower than minimal WinBlue",
+            "error: device feature 0: resume recovery time 11 ms exceeds 10 ms",
+            "error: device feature 0: resume signaling time 0 ms not in range 1..=20 ms",
+            "error: device feature 1: vendor revision must be at least 1",
+            "error: device feature 2: duplicate feature descriptor",
+            "error: configuration 0 feature 0: CCGP device descriptor is only allowed at device level",
+            "error: configuration 0 function 0 feature 0: compatible ID must consist of uppercase letters, digits and underscores padded with NULs",
+            "error: configuration 0 function 0 feature 0: sub-compatible ID must consist of uppercase letters, digits and underscores padded with NULs",
+            "warning: configuration 0 function 0 feature 1: DeviceInterfaceGUIDs should be RegMultiSz (or use DeviceInterfaceGUID for RegSz)",
+            "error: configuration 0 function 0 feature 1: DeviceInterfaceGUIDs: invalid GUID string \"{1234}\"",
+            "error: configuration 0 function 0 feature 2: property name must be a non-empty NULL-terminated UTF-16 string",
+        ]);
+    }
+
+    #[test]
+    fn too_large_set() {
+        let mut set = DescriptorSet::from(&EXAMPLE_SET);
+        set.features.push(FeatureDescriptor::RegistryProperty {
+            data_type: PropertyDataType::RegBinary,
+            name: utf16("Blob\0"),
+            data: vec![0; 70000],
+        });
+        assert_eq!(set.to_bytes(), Err(crate::os_20::Error::TooLarge));
+        assert_eq!(messages(&set)[0],
+            "error: descriptor set: descriptor set size 70198 exceeds 65535");
+    }
+}
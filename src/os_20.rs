@@ -1,13 +1,43 @@
-use usb_device::descriptor::capability_type;
-
 use crate::windows_version::WindowsVersion;
 
 /// Zero indicates that alternative enumeration is not supported
 pub const ALT_ENUM_CODE_NOT_SUPPORTED: u8 = 0;
 
+/// Errors when writing or patching descriptors in runtime
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// Buffer is too small to hold the descriptor
+    BufferTooSmall,
+    /// Windows version is lower than [`WindowsVersion::MINIMAL`]
+    InvalidVersion,
+    /// Patch data does not match [`PatchSlot`] length
+    InvalidPatch,
+    /// Function subset first interface does not match the interface number of the function
+    InterfaceMismatch,
+    /// Descriptor length does not fit in its length field
+    TooLarge,
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::BufferTooSmall => f.write_str("buffer too small for descriptor"),
+            Self::InvalidVersion => f.write_str("Windows version lower than minimal allowed in MS OS 2.0 descriptors"),
+            Self::InvalidPatch => f.write_str("patch data length does not match patch slot"),
+            Self::InterfaceMismatch => f.write_str("function subset first interface does not match interface number"),
+            Self::TooLarge => f.write_str("descriptor length does not fit in its length field"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
 /// Microsoft OS 2.0 descriptor wIndex values
 #[repr(u16)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum DescriptorIndex {
     /// MS OS 2.0 retrieve descriptor request
     Descriptor = 0x07,
@@ -17,7 +47,8 @@ pub enum DescriptorIndex {
 
 /// Microsoft OS 2.0 descriptor types
 #[repr(u16)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[allow(missing_docs)]
 pub enum DescriptorType {
     SetHeaderDescriptor = 0x00,
@@ -36,11 +67,29 @@ impl DescriptorType {
     pub const fn bytes(&self) -> [u8; 2] {
         (*self as u16).to_le_bytes()
     }
+
+    /// Get descriptor type from wDescriptorType value
+    pub const fn from_u16(value: u16) -> Option<Self> {
+        Some(match value {
+            0x00 => Self::SetHeaderDescriptor,
+            0x01 => Self::SubsetHeaderConfiguration,
+            0x02 => Self::SubsetHeaderFunction,
+            0x03 => Self::FeatureCompatbleId,
+            0x04 => Self::FeatureRegProperty,
+            0x05 => Self::FeatureMinResumeTime,
+            0x06 => Self::FeatureModelId,
+            0x07 => Self::FeatureCcgpDevice,
+            0x08 => Self::FeatureVendorRevision,
+            _ => return None,
+        })
+    }
 }
 
 /// Registry Property type
 #[repr(u16)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PropertyDataType {
     /// A NULL-terminated Unicode String (REG_SZ)
     RegSz = 1,
@@ -55,6 +104,7 @@ pub enum PropertyDataType {
     /// A NULL-terminated Unicode string that contains a symbolic link (REG_LINK)
     RegLink = 6,
     /// Multiple NULL-terminated Unicode strings (REG_MULTI_SZ)
+    #[cfg_attr(feature = "serde", serde(rename = "RegMultiSz", alias = "RegMutliSz"))]
     RegMutliSz = 7,
 }
 
@@ -63,15 +113,33 @@ impl PropertyDataType {
     pub const fn bytes(&self) -> [u8; 2] {
         (*self as u16).to_le_bytes()
     }
+
+    /// Get property type from wPropertyDataType value
+    pub const fn from_u16(value: u16) -> Option<Self> {
+        Some(match value {
+            1 => Self::RegSz,
+            2 => Self::RegExpandSz,
+            3 => Self::RegBinary,
+            4 => Self::RegDwordLittleEndian,
+            5 => Self::RegDwordBigEndian,
+            6 => Self::RegLink,
+            7 => Self::RegMutliSz,
+            _ => return None,
+        })
+    }
 }
 
 /// Platform BOS capability info set
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Capabilities {
     /// Capability information for each MS OS 2.0 descriptor set
     pub infos: &'static [CapabilityInfo],
 }
 
 /// Contains information about a unique Microsoft OS 2.0 descriptor set
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct CapabilityInfo {
     /// MS OS 2.0 descriptor set for this capability
     pub descriptors: &'static DescriptorSet,
@@ -80,6 +148,8 @@ pub struct CapabilityInfo {
 }
 
 /// MS OS 2.0 descriptor set
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct DescriptorSet {
     /// Minimum Windows version for which descriptor set applies
     pub version: WindowsVersion,
@@ -90,6 +160,8 @@ pub struct DescriptorSet {
 }
 
 /// MS OS 2.0 configuration subset
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct ConfigurationSubset {
     /// bConfigurationValue
     pub configuration: u8,
@@ -102,6 +174,8 @@ pub struct ConfigurationSubset {
 /// MS OS 2.0 function subset
 ///
 /// Only used for composite devices or single-function devices that use Usbccgp.sys as client driver.
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct FunctionSubset {
     /// Interface number for the first interface of the function to which this subset applies
     pub first_interface: u8,
@@ -110,6 +184,9 @@ pub struct FunctionSubset {
 }
 
 /// MS OS 2.0 feature descriptor
+///
+/// [`core::fmt::Debug`] shows registry property names as strings instead of raw UTF-16 values.
+#[derive(PartialEq, Eq)]
 pub enum FeatureDescriptor {
     /// Define a compatible device ID
     CompatibleId {
@@ -153,13 +230,18 @@ pub enum FeatureDescriptor {
     },
 }
 
-/// Implement slice_total_len `const fn` for a type using Self::total_len()
+/// Implement slice_total_len and slice_prefix_len `const fn` for a type using Self::total_len()
 macro_rules! impl_slice_total_len {
     () => {
-        const fn slice_total_len(items: &[Self]) -> u16 {
+        const fn slice_total_len(items: &[Self]) -> usize {
+            Self::slice_prefix_len(items, items.len())
+        }
+
+        /// Total length of the first `n` items
+        const fn slice_prefix_len(items: &[Self], n: usize) -> usize {
             let mut size = 0;
             let mut i = 0;
-            while i < items.len() {
+            while i < n {
                 size += items[i].total_len();
                 i += 1;
             }
@@ -202,10 +284,13 @@ macro_rules! slice_assign {
 }
 
 /// Start writing a descriptor by filling first 4 bytes with wLength and wDescriptorType
+///
+/// Lengths are computed as `usize`, callers must check that the total length of the descriptor
+/// set fits in u16 (see [`DescriptorSet::check_len`]), then all the other lengths fit too.
 macro_rules! descriptor_start {
     ($buf:ident, $pos:ident, [$len:expr, $desc_type:expr]) => {
         {
-            let length = $len.to_le_bytes();
+            let length = ($len as u16).to_le_bytes();
             let descriptor_type = $desc_type.bytes();
             slice_assign!($buf[$pos, $pos + 2] = length[0, 2]);
             slice_assign!($buf[$pos + 2, $pos + 4] = descriptor_type[0, 2]);
@@ -215,6 +300,9 @@ macro_rules! descriptor_start {
 }
 
 /// Write feature descriptor to buffer and advance position
+///
+/// `FeatureDescriptor` is resolved at the call site, so this works for both borrowed and owned
+/// descriptor structures, as long as the feature implements `total_len` and `descriptor_type`.
 macro_rules! feature_descriptor {
     ($buf:ident, $pos:ident, $feature:expr) => {
         {
@@ -228,7 +316,7 @@ macro_rules! feature_descriptor {
                 },
                 FeatureDescriptor::RegistryProperty { data_type, name, data } => {
                     let dtype = (*data_type as u16).to_le_bytes();
-                    let name_len = (2 * name.len() as u16).to_le_bytes();
+                    let name_len = ((2 * name.len()) as u16).to_le_bytes();
                     let data_len = (data.len() as u16).to_le_bytes();
 
                     slice_assign!($buf[$pos, $pos + 2] = dtype[0, 2]);
@@ -272,15 +360,131 @@ macro_rules! feature_descriptor {
     };
 }
 
+/// Write whole descriptor set to buffer starting at position 0
+macro_rules! descriptor_set {
+    ($buf:ident, $pos:ident, $set:expr) => {
+        {
+            let set = $set;
+
+            // Descriptor set header
+            descriptor_start!($buf, $pos, [$crate::os_20::DescriptorSet::HEADER_SIZE, $crate::os_20::DescriptorType::SetHeaderDescriptor]);
+            let total_len = (set.total_len() as u16).to_le_bytes();
+            let ver = set.version.bytes();
+            slice_assign!($buf[4, 8] = ver[0, 4]);
+            slice_assign!($buf[8, 10] = total_len[0, 2]);
+            $pos += 6;
+
+            // Device-level feature descriptors
+            let mut f = 0;
+            while f < set.features.len() {
+                feature_descriptor!($buf, $pos, set.features[f]);
+                f += 1;
+            }
+
+            // Configuration subsets
+            let mut c = 0;
+            while c < set.configurations.len() {
+                let config = &set.configurations[c];
+
+                // Configuration subset header
+                descriptor_start!($buf, $pos, [$crate::os_20::ConfigurationSubset::HEADER_SIZE, $crate::os_20::DescriptorType::SubsetHeaderConfiguration]);
+                $buf[$pos] = config.configuration;  // bConfigurationValue
+                $buf[$pos + 1] = 0; // bReserved
+                let total_len = (config.total_len() as u16).to_le_bytes();
+                slice_assign!($buf[$pos + 2, $pos + 4] = total_len[0, 2]);
+                $pos += 4;
+
+                // Configuration-level feature descriptors
+                let mut f = 0;
+                while f < config.features.len() {
+                    feature_descriptor!($buf, $pos, config.features[f]);
+                    f += 1;
+                }
+
+                // Function subsets
+                let mut fun = 0;
+                while fun < config.functions.len() {
+                    let function = &config.functions[fun];
+
+                    // Function subset header
+                    descriptor_start!($buf, $pos, [$crate::os_20::FunctionSubset::HEADER_SIZE, $crate::os_20::DescriptorType::SubsetHeaderFunction]);
+                    $buf[$pos] = function.first_interface;  // bFirstInterface
+                    $buf[$pos + 1] = 0; // bReserved
+                    let total_len = (function.total_len() as u16).to_le_bytes();
+                    slice_assign!($buf[$pos + 2, $pos + 4] = total_len[0, 2]);
+                    $pos += 4;
+
+                    // Function-level feature descriptors
+                    let mut f = 0;
+                    while f < function.features.len() {
+                        feature_descriptor!($buf, $pos, function.features[f]);
+                        f += 1;
+                    }
+
+                    fun += 1;
+                }
+
+                c += 1;
+            }
+        }
+    };
+}
+
+/// Check that bLength of BOS capability and wTotalLength of all descriptor sets fit in their fields
+macro_rules! capabilities_check_len {
+    ($caps:expr) => {
+        {
+            let caps = $caps;
+            let total_len = $crate::os_20::Capabilities::HEADER_SIZE
+                + caps.infos.len() * $crate::os_20::CapabilityInfo::TOTAL_LEN;
+            let mut result = if total_len > u8::MAX as usize { Err($crate::os_20::Error::TooLarge) } else { Ok(()) };
+            let mut i = 0;
+            while result.is_ok() && i < caps.infos.len() {
+                if caps.infos[i].descriptors.check_len().is_err() {
+                    result = Err($crate::os_20::Error::TooLarge);
+                }
+                i += 1;
+            }
+            result
+        }
+    };
+}
+
+/// Write BOS capability data (without the first 3 bytes) to buffer starting at position 0
+macro_rules! capabilities_data {
+    ($buf:ident, $pos:ident, $caps:expr) => {
+        {
+            let caps = $caps;
+
+            $buf[0] = 0; // bReserved
+            slice_assign!($buf[1, 17] = $crate::os_20::Capabilities::CAPABILITY_ID[0, 16]); // MS_OS_20_Platform_Capability_ID
+            $pos += 17;
+
+            let mut i = 0;
+            while i < caps.infos.len() {
+                let info = &caps.infos[i];
+                let version = info.descriptors.version.bytes();
+                let total_len = (info.descriptors.total_len() as u16).to_le_bytes();
+                slice_assign!($buf[$pos, $pos + 4] = version[0, 4]);
+                slice_assign!($buf[$pos + 4, $pos + 6] = total_len[0, 2]);
+                $buf[$pos + 6] = $crate::os_20::Capabilities::vendor_code_descriptor_set(i as u8);
+                $buf[$pos + 7] = info.alt_enum_cmd;
+                $pos += 8;
+                i += 1;
+            }
+        }
+    };
+}
+
 impl ConfigurationSubset {
     /// Get total size of descriptor
     pub const fn size(&self) -> usize {
-        self.total_len() as usize
+        self.total_len()
     }
 
-    const HEADER_SIZE: u16 = 8;
+    const HEADER_SIZE: usize = 8;
 
-    const fn total_len(&self) -> u16 {
+    const fn total_len(&self) -> usize {
         Self::HEADER_SIZE
             + FeatureDescriptor::slice_total_len(self.features)
             + FunctionSubset::slice_total_len(self.functions)
@@ -292,14 +496,14 @@ impl ConfigurationSubset {
 impl FunctionSubset {
     /// Get total size of descriptor
     pub const fn size(&self) -> usize {
-        self.total_len() as usize
+        self.total_len()
     }
 
-    const HEADER_SIZE: u16 = 8;
+    const HEADER_SIZE: usize = 8;
 
     impl_slice_total_len!();
 
-    const fn total_len(&self) -> u16 {
+    const fn total_len(&self) -> usize {
         Self::HEADER_SIZE + FeatureDescriptor::slice_total_len(self.features)
     }
 }
@@ -307,14 +511,14 @@ impl FunctionSubset {
 impl FeatureDescriptor {
     /// Get total size of descriptor
     pub const fn size(&self) -> usize {
-        self.total_len() as usize
+        self.total_len()
     }
 
-    const fn total_len(&self) -> u16 {
+    const fn total_len(&self) -> usize {
         match self {
             Self::CompatibleId { .. } => 2 + 2 + 8 + 8,
             Self::RegistryProperty { name, data, .. } => {
-                2 + 2 + 2 + 2 + 2 + (2 * name.len() + data.len()) as u16
+                2 + 2 + 2 + 2 + 2 + 2 * name.len() + data.len()
             },
             Self::ResumeTime { .. } => 2 + 2 + 1 + 1,
             Self::ModelId { .. } => 2 + 2 + 16,
@@ -346,10 +550,120 @@ impl FeatureDescriptor {
     }
 }
 
+/// Formats UTF-16 string as a quoted string, with unpaired surrogates replaced by U+FFFD
+pub(crate) struct Utf16Str<'a>(pub(crate) &'a [u16]);
+
+impl core::fmt::Debug for Utf16Str<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        use core::fmt::Write;
+
+        f.write_char('"')?;
+        for c in char::decode_utf16(self.0.iter().copied()) {
+            let c = c.unwrap_or(char::REPLACEMENT_CHARACTER);
+            for e in c.escape_debug() {
+                f.write_char(e)?;
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Utf16Str<'_> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "\"");
+        for c in char::decode_utf16(self.0.iter().copied()) {
+            match c.unwrap_or(char::REPLACEMENT_CHARACTER) {
+                '\0' => defmt::write!(f, "\\0"),
+                c => defmt::write!(f, "{}", c),
+            }
+        }
+        defmt::write!(f, "\"");
+    }
+}
+
+/// Feature descriptor fields borrowed from either [`FeatureDescriptor`] or its owned version,
+/// used to share their [`core::fmt::Debug`] implementation
+pub(crate) enum FeatureFields<'a> {
+    CompatibleId { id: &'a [u8; 8], sub_id: &'a [u8; 8] },
+    RegistryProperty { data_type: PropertyDataType, name: &'a [u16], data: &'a [u8] },
+    ResumeTime { recovery: u8, signaling: u8 },
+    ModelId { id: &'a [u8; 16] },
+    CcgpDevice,
+    VendorRevision { revision: u16 },
+}
+
+impl core::fmt::Debug for FeatureFields<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::CompatibleId { id, sub_id } => f.debug_struct("CompatibleId")
+                .field("id", id)
+                .field("sub_id", sub_id)
+                .finish(),
+            Self::RegistryProperty { data_type, name, data } => f.debug_struct("RegistryProperty")
+                .field("data_type", data_type)
+                .field("name", &Utf16Str(name))
+                .field("data", data)
+                .finish(),
+            Self::ResumeTime { recovery, signaling } => f.debug_struct("ResumeTime")
+                .field("recovery", recovery)
+                .field("signaling", signaling)
+                .finish(),
+            Self::ModelId { id } => f.debug_struct("ModelId")
+                .field("id", id)
+                .finish(),
+            Self::CcgpDevice => f.write_str("CcgpDevice"),
+            Self::VendorRevision { revision } => f.debug_struct("VendorRevision")
+                .field("revision", revision)
+                .finish(),
+        }
+    }
+}
+
+impl FeatureDescriptor {
+    pub(crate) const fn fields(&self) -> FeatureFields<'static> {
+        match *self {
+            Self::CompatibleId { id, sub_id } => FeatureFields::CompatibleId { id, sub_id },
+            Self::RegistryProperty { data_type, name, data } => FeatureFields::RegistryProperty { data_type, name, data },
+            Self::ResumeTime { recovery, signaling } => FeatureFields::ResumeTime { recovery, signaling },
+            Self::ModelId { id } => FeatureFields::ModelId { id },
+            Self::CcgpDevice => FeatureFields::CcgpDevice,
+            Self::VendorRevision { revision } => FeatureFields::VendorRevision { revision },
+        }
+    }
+}
+
+impl core::fmt::Debug for FeatureDescriptor {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.fields().fmt(f)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for FeatureDescriptor {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            Self::CompatibleId { id, sub_id } => {
+                defmt::write!(f, "CompatibleId {{ id: {=[u8]}, sub_id: {=[u8]} }}", id[..], sub_id[..])
+            },
+            Self::RegistryProperty { data_type, name, data } => {
+                defmt::write!(f, "RegistryProperty {{ data_type: {}, name: {}, data: {=[u8]} }}",
+                    data_type, Utf16Str(name), data)
+            },
+            Self::ResumeTime { recovery, signaling } => {
+                defmt::write!(f, "ResumeTime {{ recovery: {}, signaling: {} }}", recovery, signaling)
+            },
+            Self::ModelId { id } => defmt::write!(f, "ModelId {{ id: {=[u8]} }}", id[..]),
+            Self::CcgpDevice => defmt::write!(f, "CcgpDevice"),
+            Self::VendorRevision { revision } => defmt::write!(f, "VendorRevision {{ revision: {} }}", revision),
+        }
+    }
+}
+
 impl DescriptorSet {
-    const HEADER_SIZE: u16 = 10;
+    const HEADER_SIZE: usize = 10;
 
-    const fn total_len(&self) -> u16 {
+    const fn total_len(&self) -> usize {
         Self::HEADER_SIZE
             + FeatureDescriptor::slice_total_len(self.features)
             + ConfigurationSubset::slice_total_len(self.configurations)
@@ -357,7 +671,7 @@ impl DescriptorSet {
 
     /// Get total size of descriptor
     pub const fn size(&self) -> usize {
-        self.total_len() as usize
+        self.total_len()
     }
 
     /// Get descriptor array in compile time
@@ -366,84 +680,268 @@ impl DescriptorSet {
     /// using `self` to retrive this value automatically. Use [`Self::size`] method
     /// to get the correct value for the descriptor array length.
     pub const fn descriptor<const N: usize>(&self) -> [u8; N] {
+        if self.check_len().is_err() {
+            panic!("Descriptor set too large, wTotalLength does not fit in u16");
+        }
         let mut buf = [0; N];
         let mut pos = 0;
+        descriptor_set!(buf, pos, self);
+        let _ = pos; // avoid warning `pos is never read`
+        buf
+    }
 
-        // Descriptor set header
-        descriptor_start!(buf, pos, [Self::HEADER_SIZE, DescriptorType::SetHeaderDescriptor]);
-        let total_len = self.total_len().to_le_bytes();
-        let ver = self.version.bytes();
-        slice_assign!(buf[4, 8] = ver[0, 4]);
-        slice_assign!(buf[8, 10] = total_len[0, 2]);
-        pos += 6;
-
-        // Device-level feature descriptors
-        let mut f = 0;
-        while f < self.features.len() {
-            feature_descriptor!(buf, pos, self.features[f]);
-            f += 1;
-        }
-
-        // Configuration subsets
-        let mut c = 0;
-        while c < self.configurations.len() {
-            let config = &self.configurations[c];
-
-            // Configuration subset header
-            descriptor_start!(buf, pos, [ConfigurationSubset::HEADER_SIZE, DescriptorType::SubsetHeaderConfiguration]);
-            buf[pos] = config.configuration;  // bConfigurationValue
-            buf[pos + 1] = 0; // bReserved
-            let total_len = config.total_len().to_le_bytes();
-            slice_assign!(buf[pos + 2, pos + 4] = total_len[0, 2]);
-            pos += 4;
-
-            // Configuration-level feature descriptors
-            let mut f = 0;
-            while f < config.features.len() {
-                feature_descriptor!(buf, pos, config.features[f]);
-                f += 1;
-            }
+    /// Write descriptor to a buffer in runtime
+    ///
+    /// This is the runtime equivalent of [`Self::descriptor`] for descriptor sets that are not known
+    /// at compile time. Returns the number of bytes written (equal to [`Self::size`]).
+    pub fn write_to(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        if !self.version.is_allowed() {
+            return Err(Error::InvalidVersion);
+        }
+        self.check_len()?;
+        let size = self.size();
+        if buf.len() < size {
+            return Err(Error::BufferTooSmall);
+        }
+
+        let mut pos = 0;
+        descriptor_set!(buf, pos, self);
+        Ok(pos)
+    }
 
-            // Function subsets
-            let mut fun = 0;
-            while fun < config.functions.len() {
-                let function = &config.functions[fun];
+    /// Check that wTotalLength fits in u16, lengths of all subsets and features are smaller
+    pub(crate) const fn check_len(&self) -> Result<(), Error> {
+        if self.total_len() > u16::MAX as usize {
+            return Err(Error::TooLarge);
+        }
+        Ok(())
+    }
+}
 
-                // Function subset header
-                descriptor_start!(buf, pos, [FunctionSubset::HEADER_SIZE, DescriptorType::SubsetHeaderFunction]);
-                buf[pos] = function.first_interface;  // bFirstInterface
-                buf[pos + 1] = 0; // bReserved
-                let total_len = function.total_len().to_le_bytes();
-                slice_assign!(buf[pos + 2, pos + 4] = total_len[0, 2]);
-                pos += 4;
+/// Location of a feature descriptor within a [`DescriptorSet`]
+///
+/// All values are indices into the corresponding slices of the descriptor set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FeaturePath {
+    /// Feature in [`DescriptorSet::features`]
+    Device {
+        /// Index of the feature
+        feature: usize,
+    },
+    /// Feature in [`ConfigurationSubset::features`]
+    Configuration {
+        /// Index of the configuration subset
+        configuration: usize,
+        /// Index of the feature
+        feature: usize,
+    },
+    /// Feature in [`FunctionSubset::features`]
+    Function {
+        /// Index of the configuration subset
+        configuration: usize,
+        /// Index of the function subset
+        function: usize,
+        /// Index of the feature
+        feature: usize,
+    },
+}
 
-                // Function-level feature descriptors
-                let mut f = 0;
-                while f < function.features.len() {
-                    feature_descriptor!(buf, pos, function.features[f]);
-                    f += 1;
-                }
+/// Range of bytes within encoded descriptor set that can be modified after generation
+///
+/// This allows to keep the descriptor set `const` and only patch per-device values (e.g. serial
+/// number based Model ID or interface GUID) at startup. Offsets are computed in compile time with
+/// [`DescriptorSet::model_id_slot`] or [`DescriptorSet::property_data_slot`]:
+///
+/// ```
+/// use usbd_microsoft_os::{os_20, WindowsVersion, utf16_lit, utf16_null_le_bytes};
+///
+/// const SET: os_20::DescriptorSet = os_20::DescriptorSet {
+///     version: WindowsVersion::MINIMAL,
+///     features: &[
+///         os_20::FeatureDescriptor::ModelId { id: &[0; 16] },
+///         os_20::FeatureDescriptor::RegistryProperty {
+///             data_type: os_20::PropertyDataType::RegMutliSz,
+///             name: &utf16_lit::utf16_null!("DeviceInterfaceGUIDs"),
+///             data: &utf16_null_le_bytes!("{00000000-0000-0000-0000-000000000000}\0"),
+///         },
+///     ],
+///     configurations: &[],
+/// };
+/// const MODEL_ID: os_20::PatchSlot = SET.model_id_slot(os_20::FeaturePath::Device { feature: 0 });
+/// const GUID: os_20::PatchSlot = SET.property_data_slot(os_20::FeaturePath::Device { feature: 1 });
+///
+/// let unique_id = [0x42; 16];
+/// let mut buf = os_20::DescriptorBuffer::<256>::from_descriptor_set(&SET).unwrap();
+/// MODEL_ID.apply(buf.as_mut_slice(), &unique_id).unwrap();
+/// GUID.apply_guid(buf.as_mut_slice(), &unique_id).unwrap();
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PatchSlot {
+    /// Offset of the first byte from the start of the descriptor set
+    pub offset: usize,
+    /// Number of bytes
+    pub len: usize,
+}
+
+impl PatchSlot {
+    /// Length of a GUID string `{xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx}` encoded as UTF-16
+    pub const GUID_UTF16_LEN: usize = 2 * 38;
+
+    /// Overwrite the slot in encoded descriptor set with `data` of the same length
+    pub fn apply(&self, descriptor_set: &mut [u8], data: &[u8]) -> Result<(), Error> {
+        if data.len() != self.len {
+            return Err(Error::InvalidPatch);
+        }
+        descriptor_set.get_mut(self.offset..self.offset + self.len)
+            .ok_or(Error::BufferTooSmall)?
+            .copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Write UUID as GUID string in UTF-16 at the start of the slot
+    ///
+    /// The `uuid` bytes are in the order as written in the string (RFC 4122 network byte order).
+    /// Remaining bytes in the slot (e.g. null terminators) are not modified, so the slot should
+    /// be created from a placeholder like `{00000000-0000-0000-0000-000000000000}`.
+    pub fn apply_guid(&self, descriptor_set: &mut [u8], uuid: &[u8; 16]) -> Result<(), Error> {
+        if self.len < Self::GUID_UTF16_LEN {
+            return Err(Error::InvalidPatch);
+        }
+        let slot = descriptor_set.get_mut(self.offset..self.offset + Self::GUID_UTF16_LEN)
+            .ok_or(Error::BufferTooSmall)?;
 
-                fun += 1;
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+        let mut chars = [0u8; 38];
+        let mut pos = 0;
+        chars[pos] = b'{';
+        pos += 1;
+        for (i, byte) in uuid.iter().enumerate() {
+            if matches!(i, 4 | 6 | 8 | 10) {
+                chars[pos] = b'-';
+                pos += 1;
             }
+            chars[pos] = HEX[(byte >> 4) as usize];
+            chars[pos + 1] = HEX[(byte & 0xf) as usize];
+            pos += 2;
+        }
+        chars[pos] = b'}';
 
-            c += 1;
+        for (dst, c) in slot.chunks_exact_mut(2).zip(chars) {
+            dst.copy_from_slice(&(c as u16).to_le_bytes());
         }
+        Ok(())
+    }
+}
 
-        buf
+impl DescriptorSet {
+    /// Get feature descriptor at given location
+    ///
+    /// Panics if the location is not valid.
+    pub const fn feature(&self, path: FeaturePath) -> &FeatureDescriptor {
+        match path {
+            FeaturePath::Device { feature } => &self.features[feature],
+            FeaturePath::Configuration { configuration, feature } => {
+                &self.configurations[configuration].features[feature]
+            },
+            FeaturePath::Function { configuration, function, feature } => {
+                &self.configurations[configuration].functions[function].features[feature]
+            },
+        }
+    }
+
+    /// Offset of configuration subset header from the start of the descriptor set
+    const fn configuration_offset(&self, configuration: usize) -> usize {
+        Self::HEADER_SIZE
+            + FeatureDescriptor::slice_total_len(self.features)
+            + ConfigurationSubset::slice_prefix_len(self.configurations, configuration)
+    }
+
+    /// Offset of function subset header from the start of the descriptor set
+    const fn function_offset(&self, configuration: usize, function: usize) -> usize {
+        let config = &self.configurations[configuration];
+        self.configuration_offset(configuration)
+            + ConfigurationSubset::HEADER_SIZE
+            + FeatureDescriptor::slice_total_len(config.features)
+            + FunctionSubset::slice_prefix_len(config.functions, function)
+    }
+
+    /// Get offset of feature descriptor at given location from the start of the descriptor set
+    ///
+    /// Panics if the location is not valid.
+    pub const fn feature_offset(&self, path: FeaturePath) -> usize {
+        let _ = self.feature(path); // validate path
+        match path {
+            FeaturePath::Device { feature } => {
+                Self::HEADER_SIZE + FeatureDescriptor::slice_prefix_len(self.features, feature)
+            },
+            FeaturePath::Configuration { configuration, feature } => {
+                let config = &self.configurations[configuration];
+                self.configuration_offset(configuration)
+                    + ConfigurationSubset::HEADER_SIZE
+                    + FeatureDescriptor::slice_prefix_len(config.features, feature)
+            },
+            FeaturePath::Function { configuration, function, feature } => {
+                let fun = &self.configurations[configuration].functions[function];
+                self.function_offset(configuration, function)
+                    + FunctionSubset::HEADER_SIZE
+                    + FeatureDescriptor::slice_prefix_len(fun.features, feature)
+            },
+        }
+    }
+
+    /// Get slot with bFirstInterface of function subset
+    ///
+    /// Can be used to set the interface number allocated in runtime. Panics if the function
+    /// subset does not exist.
+    pub const fn first_interface_slot(&self, configuration: usize, function: usize) -> PatchSlot {
+        let _ = &self.configurations[configuration].functions[function]; // validate indices
+        PatchSlot {
+            // wLength, wDescriptorType
+            offset: self.function_offset(configuration, function) + 2 + 2,
+            len: 1,
+        }
+    }
+
+    /// Get slot with ModelId of [`FeatureDescriptor::ModelId`] at given location
+    ///
+    /// Panics if the location is not valid or it is not a Model ID descriptor.
+    pub const fn model_id_slot(&self, path: FeaturePath) -> PatchSlot {
+        match self.feature(path) {
+            FeatureDescriptor::ModelId { id } => PatchSlot {
+                offset: self.feature_offset(path) + 4,
+                len: id.len(),
+            },
+            _ => panic!("Feature descriptor is not ModelId"),
+        }
+    }
+
+    /// Get slot with PropertyData of [`FeatureDescriptor::RegistryProperty`] at given location
+    ///
+    /// Panics if the location is not valid or it is not a registry property descriptor.
+    pub const fn property_data_slot(&self, path: FeaturePath) -> PatchSlot {
+        match self.feature(path) {
+            FeatureDescriptor::RegistryProperty { name, data, .. } => PatchSlot {
+                // wLength, wDescriptorType, wPropertyDataType, wPropertyNameLength, PropertyName, wPropertyDataLength
+                offset: self.feature_offset(path) + 2 + 2 + 2 + 2 + 2 * name.len() + 2,
+                len: data.len(),
+            },
+            _ => panic!("Feature descriptor is not RegistryProperty"),
+        }
     }
 }
 
 impl CapabilityInfo {
-    const TOTAL_LEN: u8 = 4 + 2 + 1 + 1;
+    const TOTAL_LEN: usize = 4 + 2 + 1 + 1;
 }
 
 impl Capabilities {
-    const HEADER_SIZE: u8 = 4 + Self::CAPABILITY_ID.len() as u8;
+    const HEADER_SIZE: usize = 4 + Self::CAPABILITY_ID.len();
 
     // PlatformCapabilityUUID = D8DD60DF-4589-4CC7-9CD2-659D9E648A9F
     // For encoding rules ("fields" as little-endian) see: https://www.rfc-editor.org/rfc/rfc4122
-    const CAPABILITY_ID: [u8; 16] = [
+    pub(crate) const CAPABILITY_ID: [u8; 16] = [
         0xDF, 0x60, 0xDD, 0xD8,
         0x89, 0x45,
         0xC7, 0x4C,
@@ -452,91 +950,159 @@ impl Capabilities {
         0x65, 0x9D, 0x9E, 0x64, 0x8A, 0x9F,
     ];
 
-    const fn total_len(&self) -> u8 {
-        Self::HEADER_SIZE + self.infos.len() as u8 * CapabilityInfo::TOTAL_LEN
+    const fn total_len(&self) -> usize {
+        Self::HEADER_SIZE + self.infos.len() * CapabilityInfo::TOTAL_LEN
     }
 
+    /// Check that bLength of the capability and wTotalLength of all descriptor sets fit in their fields
+    const fn check_len(&self) -> Result<(), Error> {
+        capabilities_check_len!(self)
+    }
 
-    const fn vendor_code_descriptor_set(index: u8) -> u8 {
+    /// Get bMS_VendorCode used to request descriptor set at given index in [`Self::infos`]
+    pub(crate) const fn vendor_code_descriptor_set(index: u8) -> u8 {
         index + 1
     }
 
-    pub(crate) fn vendor_code_to_descriptor_set(vendor_code: u8) -> Option<usize> {
-        vendor_code.checked_sub(1).map(|v| v as usize)
+    /// Get index of descriptor set (in [`Self::infos`]) requested using given bMS_VendorCode
+    pub const fn vendor_code_to_descriptor_set(vendor_code: u8) -> Option<usize> {
+        match vendor_code.checked_sub(1) {
+            Some(v) => Some(v as usize),
+            None => None,
+        }
     }
 
     /// Capability type passed to [`usb_device::descriptor::BosWriter`]'s `capability` method
-    pub const CAPABILITY_TYPE: u8 = capability_type::PLATFORM;
+    ///
+    /// This is the Platform capability type (`usb_device::descriptor::capability_type::PLATFORM`).
+    pub const CAPABILITY_TYPE: u8 = 0x05;
 
     /// Size of data as passed to [`usb_device::descriptor::BosWriter`]'s `capability` method
     pub const fn data_len(&self) -> usize {
-        (self.total_len() - 3) as usize
+        self.total_len() - 3
     }
 
     /// Data passed to [`usb_device::descriptor::BosWriter`]'s `capability` method
     pub const fn descriptor_data<const N: usize>(&self) -> [u8; N] {
+        if self.check_len().is_err() {
+            panic!("Capability data too large, too many descriptor sets or descriptor set too large");
+        }
         let mut buf = [0u8; N];
         let mut pos = 0;
+        capabilities_data!(buf, pos, self);
+        let _ = pos; // avoid warning `pos is never read`
+        buf
+    }
 
-        buf[0] = 0; // bReserved
-        slice_assign!(buf[1, 17] = Self::CAPABILITY_ID[0, 16]); // MS_OS_20_Platform_Capability_ID
-        pos += 17;
-
-        let mut i = 0;
-        while i < self.infos.len() {
-            let info = &self.infos[i];
-            let version = info.descriptors.version.bytes();
-            let total_len = info.descriptors.total_len().to_le_bytes();
-            slice_assign!(buf[pos, pos + 4] = version[0, 4]);
-            slice_assign!(buf[pos + 4, pos + 6] = total_len[0, 2]);
-            buf[pos + 6] = Self::vendor_code_descriptor_set(i as u8);
-            buf[pos + 7] = info.alt_enum_cmd;
-            pos += 8;
-            i += 1;
+    /// Write capability data to a buffer in runtime
+    ///
+    /// This is the runtime equivalent of [`Self::descriptor_data`]. Returns the number of bytes
+    /// written (equal to [`Self::data_len`]).
+    pub fn write_data_to(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        if self.infos.iter().any(|info| !info.descriptors.version.is_allowed()) {
+            return Err(Error::InvalidVersion);
+        }
+        self.check_len()?;
+        if buf.len() < self.data_len() {
+            return Err(Error::BufferTooSmall);
         }
 
-        buf
+        let mut pos = 0;
+        capabilities_data!(buf, pos, self);
+        Ok(pos)
     }
 }
 
+/// Fixed-capacity buffer for descriptor data generated in runtime
+///
+/// Can be used as storage in [`crate::class::DynamicMsOsUsbClass`]. The data can be modified
+/// after it has been generated, e.g. to patch per-device values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DescriptorBuffer<const N: usize> {
+    data: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> DescriptorBuffer<N> {
+    /// Create an empty buffer
+    pub const fn new() -> Self {
+        Self { data: [0; N], len: 0 }
+    }
+
+    /// Create buffer with data of a descriptor set using [`DescriptorSet::write_to`]
+    pub fn from_descriptor_set(set: &DescriptorSet) -> Result<Self, Error> {
+        let mut buf = Self::new();
+        buf.len = set.write_to(&mut buf.data)?;
+        Ok(buf)
+    }
+
+    /// Create buffer with capabilities data using [`Capabilities::write_data_to`]
+    pub fn from_capabilities(capabilities: &Capabilities) -> Result<Self, Error> {
+        let mut buf = Self::new();
+        buf.len = capabilities.write_data_to(&mut buf.data)?;
+        Ok(buf)
+    }
+
+    /// Create buffer by copying data from a slice
+    pub fn from_slice(data: &[u8]) -> Result<Self, Error> {
+        let mut buf = Self::new();
+        buf.data.get_mut(..data.len())
+            .ok_or(Error::BufferTooSmall)?
+            .copy_from_slice(data);
+        buf.len = data.len();
+        Ok(buf)
+    }
+
+    /// Get the data as mutable slice
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.data[..self.len]
+    }
+}
+
+impl<const N: usize> Default for DescriptorBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for DescriptorBuffer<N> {
+    fn as_ref(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+impl<const N: usize> core::ops::Deref for DescriptorBuffer<N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_ref()
+    }
+}
+
+/// Annotated hex dumps of encoded descriptors
+pub mod dump;
+/// Owned descriptor structures
+#[cfg(feature = "alloc")]
+pub mod owned;
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use crate::utf16_null_le_bytes;
 
     use super::*;
     use std::{format, println};
     use std::vec::Vec;
 
-    fn diff(data1: &[u8], data2: &[u8]) {
-        if data1.len() != data2.len() {
-            println!("Data length mismatch: {} vs {}", data1.len(), data2.len());
-        }
-
-        println!("    | RESULT     | EXPECTED   |");
-        println!("POS | DEC  HEX C | DEC  HEX C |");
-        println!("-------------------------------");
-        let fmt = |val: Option<&u8>| {
-            if let Some(val) = val {
-                let ch = if val.is_ascii_graphic() {
-                    char::from(*val)
-                } else {
-                    ' '
-                };
-                format!("{0:3} 0x{0:02x} {1}", val, ch)
-            } else {
-                " ".repeat(3 + 1 + 4 + 1 + 1)
-            }
-        };
+    /// Compare annotated dumps, so that a failure shows the mismatching fields
+    fn assert_set_eq(data: &[u8], expected: &[u8]) {
+        assert_eq!(format!("{}", dump::DescriptorSetDump(data)), format!("{}", dump::DescriptorSetDump(expected)));
+        assert_eq!(data, expected);
+    }
 
-        let n = data1.len().max(data2.len());
-        for i in 0..n {
-            let diff = if data1.get(i) != data2.get(i) {
-                "<== DIFF"
-            } else {
-                ""
-            };
-            println!("{:3} | {} | {} | {}", i, fmt(data1.get(i)), fmt(data2.get(i)), diff);
-        }
+    /// Compare annotated dumps of capability data, see [`assert_set_eq`]
+    fn assert_capability_eq(data: &[u8], expected: &[u8]) {
+        assert_eq!(format!("{}", dump::CapabilityDump(data)), format!("{}", dump::CapabilityDump(expected)));
+        assert_eq!(data, expected);
     }
 
     #[test]
@@ -553,7 +1119,7 @@ mod tests {
         assert_eq!(DESCRIPTOR.total_len(), 0x0084);
     }
 
-    const EXAMPLE_SET: DescriptorSet = DescriptorSet {
+    pub(crate) const EXAMPLE_SET: DescriptorSet = DescriptorSet {
         version: WindowsVersion::MINIMAL,
         features: &[],
         configurations: &[
@@ -624,11 +1190,10 @@ mod tests {
         assert_eq!(expected_bytes.len(), 0x00b2);
 
         // Constants
-        const SIZE: u16 = EXAMPLE_SET.total_len();
-        const DESC: [u8; SIZE as usize] = EXAMPLE_SET.descriptor();
+        const SIZE: usize = EXAMPLE_SET.total_len();
+        const DESC: [u8; SIZE] = EXAMPLE_SET.descriptor();
 
-        diff(&DESC, expected_bytes.as_slice());
-        assert_eq!(&DESC, expected_bytes.as_slice());
+        assert_set_eq(&DESC, &expected_bytes);
         assert_eq!(SIZE, 0xb2);
     }
 
@@ -711,31 +1276,138 @@ mod tests {
         assert_eq!(DESC, [6, 0, 8, 0, 0xaa, 0x11]);
     }
 
-    fn write_descriptor_set(buf: &mut [u8]) -> Result<usize, usb_device::UsbError> {
-        const SIZE: usize = EXAMPLE_SET.size();
-        const DESC: [u8; SIZE] = EXAMPLE_SET.descriptor();
+    #[test]
+    fn descriptor_set_eq() {
+        const OTHER: DescriptorSet = DescriptorSet {
+            version: WindowsVersion::MINIMAL,
+            features: &[],
+            configurations: &[],
+        };
+        assert_eq!(EXAMPLE_SET, EXAMPLE_SET);
+        assert_ne!(EXAMPLE_SET, OTHER);
+        assert_ne!(EXAMPLE_SET.configurations[0].functions[0].features[0],
+            EXAMPLE_SET.configurations[0].functions[0].features[1]);
+    }
 
-        if buf.len() < SIZE {
-            return Err(usb_device::UsbError::BufferOverflow);
-        }
+    #[test]
+    fn feature_descriptor_debug() {
+        const FEAT: FeatureDescriptor = FeatureDescriptor::RegistryProperty {
+            data_type: PropertyDataType::RegDwordLittleEndian,
+            name: &utf16_lit::utf16_null!("SelectiveSuspendEnabled"),
+            data: &[1, 0, 0, 0],
+        };
+        assert_eq!(
+            format!("{:?}", FEAT),
+            r#"RegistryProperty { data_type: RegDwordLittleEndian, name: "SelectiveSuspendEnabled\0", data: [1, 0, 0, 0] }"#
+        );
+        assert_eq!(format!("{:?}", FeatureDescriptor::CcgpDevice), "CcgpDevice");
+    }
+
+    #[test]
+    fn descriptor_set_write_to() {
+        const DESC: [u8; EXAMPLE_SET.size()] = EXAMPLE_SET.descriptor();
+        let mut buf = [0xffu8; 256];
+        let len = EXAMPLE_SET.write_to(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &DESC);
+        assert!(buf[len..].iter().all(|b| *b == 0xff));
+    }
+
+    #[test]
+    fn descriptor_set_write_to_errors() {
+        let mut buf = [0u8; EXAMPLE_SET.size() - 1];
+        assert_eq!(EXAMPLE_SET.write_to(&mut buf), Err(Error::BufferTooSmall));
+
+        const OLD: DescriptorSet = DescriptorSet {
+            version: WindowsVersion::Win10,
+            features: &[],
+            configurations: &[],
+        };
+        let old = DescriptorSet { version: WindowsVersion::Win8, ..OLD };
+        assert_eq!(old.write_to(&mut [0u8; 64]), Err(Error::InvalidVersion));
+    }
 
-        buf[..SIZE].copy_from_slice(&DESC);
+    #[test]
+    fn write_too_large() {
+        const LARGE: DescriptorSet = DescriptorSet {
+            version: WindowsVersion::MINIMAL,
+            features: &[FeatureDescriptor::RegistryProperty {
+                data_type: PropertyDataType::RegBinary,
+                name: &utf16_lit::utf16_null!("Data"),
+                data: &[0; 70000],
+            }],
+            configurations: &[],
+        };
+        assert_eq!(LARGE.size(), 10 + 10 + 10 + 70000);
+        assert_eq!(LARGE.write_to(&mut vec![0; LARGE.size()]), Err(Error::TooLarge));
 
-        Ok(SIZE)
+        const CAPABILITIES: Capabilities = Capabilities {
+            infos: &[CapabilityInfo { descriptors: &LARGE, alt_enum_cmd: 0 }],
+        };
+        assert_eq!(CAPABILITIES.write_data_to(&mut [0; 64]), Err(Error::TooLarge));
+
+        // bLength of the BOS capability descriptor is a single byte
+        const INFO: CapabilityInfo = CapabilityInfo { descriptors: &EXAMPLE_SET, alt_enum_cmd: 0 };
+        const MANY: Capabilities = Capabilities { infos: &[INFO; 30] };
+        assert_eq!(MANY.data_len(), 17 + 30 * 8);
+        assert_eq!(MANY.write_data_to(&mut [0; 512]), Err(Error::TooLarge));
+        assert!(Capabilities { infos: &MANY.infos[..29] }.write_data_to(&mut [0; 512]).is_ok());
     }
 
     #[test]
-    fn descriptor_set_write_overflow() {
-        // Default control endpoint size used in usb-device
-        let mut buf = [0u8; 128];
-        assert!(write_descriptor_set(buf.as_mut_slice()).is_err());
+    fn patch_slots() {
+        const SET: DescriptorSet = DescriptorSet {
+            version: WindowsVersion::MINIMAL,
+            features: &[FeatureDescriptor::CcgpDevice],
+            configurations: &[
+                ConfigurationSubset {
+                    configuration: 0,
+                    features: &[
+                        FeatureDescriptor::VendorRevision { revision: 1 },
+                        FeatureDescriptor::ModelId { id: &[0; 16] },
+                    ],
+                    functions: &[],
+                },
+                ConfigurationSubset {
+                    configuration: 1,
+                    features: &[],
+                    functions: EXAMPLE_SET.configurations[0].functions,
+                },
+            ],
+        };
+        const MODEL_ID: PatchSlot = SET.model_id_slot(FeaturePath::Configuration { configuration: 0, feature: 1 });
+        const GUID: PatchSlot = SET.property_data_slot(FeaturePath::Function { configuration: 1, function: 0, feature: 1 });
+        assert_eq!(MODEL_ID, PatchSlot { offset: 10 + 4 + 8 + 6 + 4, len: 16 });
+        assert_eq!(GUID.len, 80);
+
+        let mut buf = DescriptorBuffer::<256>::from_descriptor_set(&SET).unwrap();
+        assert_eq!(&buf[GUID.offset..GUID.offset + GUID.len],
+            &utf16_null_le_bytes!("{897d7b90-5aae-43e5-9c36-aa0f2fdbafc9}\0"));
+
+        let id = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff, 0x00];
+        MODEL_ID.apply(buf.as_mut_slice(), &id).unwrap();
+        GUID.apply_guid(buf.as_mut_slice(), &id).unwrap();
+        assert_eq!(&buf[MODEL_ID.offset..MODEL_ID.offset + 16], &id);
+        assert_eq!(&buf[GUID.offset..GUID.offset + GUID.len],
+            &utf16_null_le_bytes!("{11223344-5566-7788-99aa-bbccddeeff00}\0"));
+        assert_eq!(MODEL_ID.apply(buf.as_mut_slice(), &id[..15]), Err(Error::InvalidPatch));
+        assert_eq!(MODEL_ID.apply_guid(buf.as_mut_slice(), &id), Err(Error::InvalidPatch));
+
+        const FIRST_INTERFACE: PatchSlot = SET.first_interface_slot(1, 0);
+        assert_eq!(buf[FIRST_INTERFACE.offset], 1);
+        assert_eq!(buf[FIRST_INTERFACE.offset - 2], DescriptorType::SubsetHeaderFunction as u8);
     }
 
     #[test]
-    fn descriptor_set_no_overflow() {
-        // Control endpoint size with feature "control-buffer-256" enabled
-        let mut buf = [0u8; 256];
-        assert_eq!(write_descriptor_set(buf.as_mut_slice()).unwrap(), EXAMPLE_SET.size());
+    fn descriptor_buffer() {
+        const DESC: [u8; EXAMPLE_SET.size()] = EXAMPLE_SET.descriptor();
+        let mut buf = DescriptorBuffer::<256>::from_descriptor_set(&EXAMPLE_SET).unwrap();
+        assert_eq!(buf.as_ref(), &DESC);
+        buf.as_mut_slice()[4] = 0xaa;
+        assert_eq!(buf[4], 0xaa);
+
+        assert_eq!(DescriptorBuffer::<128>::from_descriptor_set(&EXAMPLE_SET), Err(Error::BufferTooSmall));
+        assert_eq!(DescriptorBuffer::<4>::from_slice(&[1, 2, 3]).unwrap().as_ref(), &[1, 2, 3]);
+        assert_eq!(DescriptorBuffer::<2>::from_slice(&[1, 2, 3]), Err(Error::BufferTooSmall));
     }
 
     // From specification:
@@ -820,17 +1492,23 @@ mod tests {
         fn bos_capability_descriptor() {
             const SIZE: usize = CAPABILITIES.data_len();
             const DATA: [u8; SIZE] = CAPABILITIES.descriptor_data();
-            diff(&DATA, &REF_CAPABILITIES[3..]);
-            assert_eq!(DATA, &REF_CAPABILITIES[3..]);
+            assert_capability_eq(&DATA, &REF_CAPABILITIES[3..]);
+        }
+
+        #[test]
+        fn bos_capability_write_data_to() {
+            let mut buf = [0u8; 64];
+            let len = CAPABILITIES.write_data_to(&mut buf).unwrap();
+            assert_eq!(&buf[..len], &REF_CAPABILITIES[3..]);
+            assert_eq!(CAPABILITIES.write_data_to(&mut buf[..len - 1]), Err(Error::BufferTooSmall));
         }
 
 
         #[test]
         fn descriptor_set() {
-            const SIZE: usize = DESCRIPTOR_SET.total_len() as usize;
+            const SIZE: usize = DESCRIPTOR_SET.total_len();
             const DATA: [u8; SIZE] = DESCRIPTOR_SET.descriptor();
-            diff(&DATA, &REF_DESCRIPTOR_SET);
-            assert_eq!(DATA, REF_DESCRIPTOR_SET);
+            assert_set_eq(&DATA, REF_DESCRIPTOR_SET);
         }
     }
 
@@ -979,25 +1657,22 @@ mod tests {
         fn bos_capability_descriptor() {
             const SIZE: usize = CAPABILITIES.data_len();
             const DATA: [u8; SIZE] = CAPABILITIES.descriptor_data();
-            diff(&DATA, &REF_CAPABILITIES[3..]);
-            assert_eq!(DATA, &REF_CAPABILITIES[3..]);
+            assert_capability_eq(&DATA, &REF_CAPABILITIES[3..]);
         }
 
 
         #[test]
         fn descriptor_set_0() {
-            const SIZE: usize = DESCRIPTOR_SETS[0].total_len() as usize;
+            const SIZE: usize = DESCRIPTOR_SETS[0].total_len();
             const DATA: [u8; SIZE] = DESCRIPTOR_SETS[0].descriptor();
-            diff(&DATA, REF_DESCRIPTOR_SETS[0]);
-            assert_eq!(DATA, REF_DESCRIPTOR_SETS[0]);
+            assert_set_eq(&DATA, REF_DESCRIPTOR_SETS[0]);
         }
 
         #[test]
         fn descriptor_set_1() {
-            const SIZE: usize = DESCRIPTOR_SETS[1].total_len() as usize;
+            const SIZE: usize = DESCRIPTOR_SETS[1].total_len();
             const DATA: [u8; SIZE] = DESCRIPTOR_SETS[1].descriptor();
-            diff(&DATA, REF_DESCRIPTOR_SETS[1]);
-            assert_eq!(DATA, REF_DESCRIPTOR_SETS[1]);
+            assert_set_eq(&DATA, REF_DESCRIPTOR_SETS[1]);
         }
     }
 }
@@ -0,0 +1,407 @@
+//! Annotated hex dumps of encoded descriptors
+//!
+//! Formats raw data field by field, similar to the examples in the MS OS 2.0 specification:
+//!
+//! ```text
+//! 0000: 0a 00                    wLength: 10
+//! 0002: 00 00                    wDescriptorType: MS_OS_20_SET_HEADER_DESCRIPTOR
+//! 0004: 00 00 03 06              dwWindowsVersion: 0x06030000 (WinBlue)
+//! 0008: b2 00                    wTotalLength: 178
+//! ```
+//!
+//! Fields of subsets are indented. Data that cannot be parsed (e.g. truncated descriptors) is
+//! dumped without annotations, so these can be used to inspect invalid descriptors.
+
+use core::fmt;
+
+use super::{Capabilities, DescriptorType, PropertyDataType};
+use crate::WindowsVersion;
+
+/// Number of bytes shown in a single line
+const LINE_BYTES: usize = 8;
+
+/// Annotated dump of an encoded descriptor set, use its [`fmt::Display`] implementation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DescriptorSetDump<'a>(pub &'a [u8]);
+
+/// Annotated dump of platform capability data, use its [`fmt::Display`] implementation
+///
+/// Data as passed to BOS writer, i.e. without the first 3 bytes of the capability descriptor,
+/// see [`Capabilities::descriptor_data`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CapabilityDump<'a>(pub &'a [u8]);
+
+/// How to show field value
+#[derive(Clone, Copy)]
+enum Value {
+    None,
+    Decimal,
+    DescriptorType,
+    Version,
+    DataType,
+    Ascii,
+    Utf16,
+    Dword,
+    Uuid,
+}
+
+/// Field annotation of a dumped line
+pub(crate) struct Field<'a> {
+    /// Field name
+    pub name: &'a str,
+    /// Nesting level of the descriptor (0 for set level, 1 for configuration, 2 for function)
+    pub depth: usize,
+    /// Field value, `None` for reserved fields
+    pub value: Option<FieldValue<'a>>,
+}
+
+/// Field value formatted according to field type
+pub(crate) struct FieldValue<'a> {
+    bytes: &'a [u8],
+    value: Value,
+}
+
+impl fmt::Display for FieldValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_value(f, self.bytes, self.value)
+    }
+}
+
+/// Output of dumped lines, each with at most [`LINE_BYTES`] bytes
+pub(crate) trait Sink {
+    /// Write line with bytes at given offset, annotated with field on the first line of a field
+    fn line(&mut self, offset: usize, bytes: &[u8], field: Option<Field<'_>>) -> fmt::Result;
+}
+
+impl Sink for fmt::Formatter<'_> {
+    fn line(&mut self, offset: usize, bytes: &[u8], field: Option<Field<'_>>) -> fmt::Result {
+        write!(self, "{:04x}:", offset)?;
+        for b in bytes {
+            write!(self, " {:02x}", b)?;
+        }
+        if let Some(field) = field {
+            let padding = 3 * (LINE_BYTES - bytes.len()) + 2 + 2 * field.depth;
+            write!(self, "{:1$}{2}", "", padding, field.name)?;
+            if let Some(value) = field.value {
+                write!(self, ": {}", value)?;
+            }
+        }
+        writeln!(self)
+    }
+}
+
+struct Dumper<'a, 's, S: Sink + ?Sized> {
+    sink: &'s mut S,
+    data: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a, S: Sink + ?Sized> Dumper<'a, '_, S> {
+    /// Dump field of `len` bytes if it ends before `end`, returns field data
+    fn field(&mut self, end: usize, len: usize, name: &str, value: Value) -> Result<Option<&'a [u8]>, fmt::Error> {
+        let Some(bytes) = self.data.get(self.pos..self.pos + len).filter(|_| self.pos + len <= end) else {
+            return Ok(None);
+        };
+        let (first, rest) = bytes.split_at(bytes.len().min(LINE_BYTES));
+        let value = match value {
+            Value::None => None,
+            _ => Some(FieldValue { bytes, value }),
+        };
+        self.sink.line(self.pos, first, Some(Field { name, depth: self.depth, value }))?;
+        self.pos += first.len();
+        self.raw(self.pos + rest.len())?;
+        Ok(Some(bytes))
+    }
+
+    /// Dump bytes without annotations up to `end`
+    fn raw(&mut self, end: usize) -> fmt::Result {
+        let end = end.min(self.data.len());
+        while self.pos < end {
+            let len = (end - self.pos).min(LINE_BYTES);
+            self.sink.line(self.pos, &self.data[self.pos..self.pos + len], None)?;
+            self.pos += len;
+        }
+        Ok(())
+    }
+
+    /// Dump fields in order until one of them does not fit before `end`
+    fn fields(&mut self, end: usize, fields: &[(usize, &str, Value)]) -> fmt::Result {
+        for &(len, name, value) in fields {
+            if self.field(end, len, name, value)?.is_none() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Dump single descriptor with given type, anything not covered by its fields is dumped raw
+    fn descriptor(&mut self, descriptor_type: DescriptorType, end: usize) -> fmt::Result {
+        self.fields(end, &[(2, "wLength", Value::Decimal), (2, "wDescriptorType", Value::DescriptorType)])?;
+        match descriptor_type {
+            DescriptorType::SetHeaderDescriptor => self.fields(end, &[
+                (4, "dwWindowsVersion", Value::Version),
+                (2, "wTotalLength", Value::Decimal),
+            ])?,
+            DescriptorType::SubsetHeaderConfiguration => self.fields(end, &[
+                (1, "bConfigurationValue", Value::Decimal),
+                (1, "bReserved", Value::None),
+                (2, "wTotalLength", Value::Decimal),
+            ])?,
+            DescriptorType::SubsetHeaderFunction => self.fields(end, &[
+                (1, "bFirstInterface", Value::Decimal),
+                (1, "bReserved", Value::None),
+                (2, "wSubsetLength", Value::Decimal),
+            ])?,
+            DescriptorType::FeatureCompatbleId => self.fields(end, &[
+                (8, "CompatibleID", Value::Ascii),
+                (8, "SubCompatibleID", Value::Ascii),
+            ])?,
+            DescriptorType::FeatureRegProperty => self.registry_property(end)?,
+            DescriptorType::FeatureMinResumeTime => self.fields(end, &[
+                (1, "bResumeRecoveryTime", Value::Decimal),
+                (1, "bResumeSignalingTime", Value::Decimal),
+            ])?,
+            DescriptorType::FeatureModelId => self.fields(end, &[(16, "ModelID", Value::Uuid)])?,
+            DescriptorType::FeatureCcgpDevice => {},
+            DescriptorType::FeatureVendorRevision => self.fields(end, &[(2, "VendorRevision", Value::Decimal)])?,
+        }
+        // Anything left in the descriptor (e.g. data of invalid length)
+        self.raw(end)
+    }
+
+    fn registry_property(&mut self, end: usize) -> fmt::Result {
+        let u16_value = |b: &[u8]| u16::from_le_bytes([b[0], b[1]]) as usize;
+        let Some(data_type) = self.field(end, 2, "wPropertyDataType", Value::DataType)? else {
+            return Ok(());
+        };
+        let data_value = match PropertyDataType::from_u16(u16_value(data_type) as u16) {
+            Some(PropertyDataType::RegSz | PropertyDataType::RegExpandSz | PropertyDataType::RegLink
+                | PropertyDataType::RegMutliSz) => Value::Utf16,
+            Some(PropertyDataType::RegDwordLittleEndian) => Value::Dword,
+            _ => Value::None,
+        };
+        let Some(name_len) = self.field(end, 2, "wPropertyNameLength", Value::Decimal)? else {
+            return Ok(());
+        };
+        if self.field(end, u16_value(name_len), "PropertyName", Value::Utf16)?.is_none() {
+            return Ok(());
+        }
+        let Some(data_len) = self.field(end, 2, "wPropertyDataLength", Value::Decimal)? else {
+            return Ok(());
+        };
+        self.field(end, u16_value(data_len), "PropertyData", data_value)?;
+        Ok(())
+    }
+}
+
+fn descriptor_type_name(descriptor_type: DescriptorType) -> &'static str {
+    match descriptor_type {
+        DescriptorType::SetHeaderDescriptor => "MS_OS_20_SET_HEADER_DESCRIPTOR",
+        DescriptorType::SubsetHeaderConfiguration => "MS_OS_20_SUBSET_HEADER_CONFIGURATION",
+        DescriptorType::SubsetHeaderFunction => "MS_OS_20_SUBSET_HEADER_FUNCTION",
+        DescriptorType::FeatureCompatbleId => "MS_OS_20_FEATURE_COMPATBLE_ID",
+        DescriptorType::FeatureRegProperty => "MS_OS_20_FEATURE_REG_PROPERTY",
+        DescriptorType::FeatureMinResumeTime => "MS_OS_20_FEATURE_MIN_RESUME_TIME",
+        DescriptorType::FeatureModelId => "MS_OS_20_FEATURE_MODEL_ID",
+        DescriptorType::FeatureCcgpDevice => "MS_OS_20_FEATURE_CCGP_DEVICE",
+        DescriptorType::FeatureVendorRevision => "MS_OS_20_FEATURE_VENDOR_REVISION",
+    }
+}
+
+fn write_value(f: &mut fmt::Formatter<'_>, bytes: &[u8], value: Value) -> fmt::Result {
+    let mut le = [0u8; 4];
+    le[..bytes.len().min(4)].copy_from_slice(&bytes[..bytes.len().min(4)]);
+    let int = u32::from_le_bytes(le);
+    match value {
+        Value::None => Ok(()),
+        Value::Decimal => write!(f, "{}", int),
+        Value::DescriptorType => match DescriptorType::from_u16(int as u16) {
+            Some(t) => f.write_str(descriptor_type_name(t)),
+            None => write!(f, "0x{:04x}", int),
+        },
+        Value::Version => match WindowsVersion::from_value(int) {
+            WindowsVersion::Other(_) => write!(f, "0x{:08x}", int),
+            version => write!(f, "0x{:08x} ({:?})", int, version),
+        },
+        Value::DataType => match PropertyDataType::from_u16(int as u16) {
+            Some(t) => write!(f, "{:?}", t),
+            None => write!(f, "{}", int),
+        },
+        Value::Ascii => {
+            f.write_str("\"")?;
+            for &b in bytes {
+                write!(f, "{}", core::ascii::escape_default(b))?;
+            }
+            f.write_str("\"")
+        },
+        Value::Utf16 => {
+            let units = bytes.chunks_exact(2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+            f.write_str("\"")?;
+            for c in char::decode_utf16(units) {
+                for e in c.unwrap_or(char::REPLACEMENT_CHARACTER).escape_debug() {
+                    fmt::Write::write_char(f, e)?;
+                }
+            }
+            f.write_str("\"")
+        },
+        Value::Dword => write!(f, "0x{:08x}", int),
+        Value::Uuid => {
+            // Fields of GUID are little-endian
+            let order = [3, 2, 1, 0, 5, 4, 7, 6, 8, 9, 10, 11, 12, 13, 14, 15];
+            for (i, &index) in order.iter().enumerate() {
+                if matches!(i, 4 | 6 | 8 | 10) {
+                    f.write_str("-")?;
+                }
+                write!(f, "{:02X}", bytes[index])?;
+            }
+            if bytes == Capabilities::CAPABILITY_ID {
+                f.write_str(" (MS OS 2.0)")?;
+            }
+            Ok(())
+        },
+    }
+}
+
+/// Dump descriptor set data to a sink
+pub(crate) fn descriptor_set<S: Sink + ?Sized>(sink: &mut S, data: &[u8]) -> fmt::Result {
+    let mut d = Dumper { sink, data, pos: 0, depth: 0 };
+    let u16_at = |offset: usize| data.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize);
+    let (mut config_end, mut function_end) = (0, 0);
+    while d.pos < d.data.len() {
+        let start = d.pos;
+        let descriptor = u16_at(start).zip(u16_at(start + 2).and_then(|t| DescriptorType::from_u16(t as u16)));
+        let Some((length, descriptor_type)) = descriptor.filter(|&(len, _)| len >= 4 && start + len <= d.data.len())
+        else {
+            break;
+        };
+
+        if start >= function_end {
+            d.depth = if start < config_end { 1 } else { 0 };
+        }
+        let subset_len = u16_at(start + 6).unwrap_or(0);
+        match descriptor_type {
+            DescriptorType::SubsetHeaderConfiguration => {
+                config_end = start + subset_len;
+                d.depth = 1;
+            },
+            DescriptorType::SubsetHeaderFunction => {
+                function_end = start + subset_len;
+                d.depth = 2;
+            },
+            _ => {},
+        }
+        d.descriptor(descriptor_type, start + length)?;
+    }
+    // Data that could not be parsed
+    d.raw(d.data.len())
+}
+
+/// Dump capability data to a sink
+pub(crate) fn capability<S: Sink + ?Sized>(sink: &mut S, data: &[u8]) -> fmt::Result {
+    let mut d = Dumper { sink, data, pos: 0, depth: 0 };
+    let end = d.data.len();
+    if d.field(end, 1, "bReserved", Value::None)?.is_some()
+        && d.field(end, 16, "PlatformCapabilityUUID", Value::Uuid)?.is_some()
+    {
+        while d.field(end, 4, "dwWindowsVersion", Value::Version)?.is_some()
+            && d.field(end, 2, "wMSOSDescriptorSetTotalLength", Value::Decimal)?.is_some()
+            && d.field(end, 1, "bMS_VendorCode", Value::Decimal)?.is_some()
+            && d.field(end, 1, "bAltEnumCode", Value::Decimal)?.is_some()
+        {}
+    }
+    d.raw(end)
+}
+
+impl fmt::Display for DescriptorSetDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        descriptor_set(f, self.0)
+    }
+}
+
+impl fmt::Display for CapabilityDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        capability(f, self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os_20::tests::EXAMPLE_SET;
+    use std::format;
+
+    #[test]
+    fn descriptor_set_dump() {
+        const DATA: [u8; EXAMPLE_SET.size()] = EXAMPLE_SET.descriptor();
+        let dump = format!("{}", DescriptorSetDump(&DATA));
+        assert_eq!(dump, r#"0000: 0a 00                    wLength: 10
+0002: 00 00                    wDescriptorType: MS_OS_20_SET_HEADER_DESCRIPTOR
+0004: 00 00 03 06              dwWindowsVersion: 0x06030000 (WinBlue)
+0008: b2 00                    wTotalLength: 178
+000a: 08 00                      wLength: 8
+000c: 01 00                      wDescriptorType: MS_OS_20_SUBSET_HEADER_CONFIGURATION
+000e: 00                         bConfigurationValue: 0
+000f: 00                         bReserved
+0010: a8 00                      wTotalLength: 168
+0012: 08 00                        wLength: 8
+0014: 02 00                        wDescriptorType: MS_OS_20_SUBSET_HEADER_FUNCTION
+0016: 01                           bFirstInterface: 1
+0017: 00                           bReserved
+0018: a0 00                        wSubsetLength: 160
+001a: 14 00                        wLength: 20
+001c: 03 00                        wDescriptorType: MS_OS_20_FEATURE_COMPATBLE_ID
+001e: 57 49 4e 55 53 42 00 00      CompatibleID: "WINUSB\x00\x00"
+0026: 00 00 00 00 00 00 00 00      SubCompatibleID: "\x00\x00\x00\x00\x00\x00\x00\x00"
+002e: 84 00                        wLength: 132
+0030: 04 00                        wDescriptorType: MS_OS_20_FEATURE_REG_PROPERTY
+0032: 07 00                        wPropertyDataType: RegMutliSz
+0034: 2a 00                        wPropertyNameLength: 42
+0036: 44 00 65 00 76 00 69 00      PropertyName: "DeviceInterfaceGUIDs\0"
+003e: 63 00 65 00 49 00 6e 00
+0046: 74 00 65 00 72 00 66 00
+004e: 61 00 63 00 65 00 47 00
+0056: 55 00 49 00 44 00 73 00
+005e: 00 00
+0060: 50 00                        wPropertyDataLength: 80
+0062: 7b 00 38 00 39 00 37 00      PropertyData: "{897d7b90-5aae-43e5-9c36-aa0f2fdbafc9}\0\0"
+006a: 64 00 37 00 62 00 39 00
+0072: 30 00 2d 00 35 00 61 00
+007a: 61 00 65 00 2d 00 34 00
+0082: 33 00 65 00 35 00 2d 00
+008a: 39 00 63 00 33 00 36 00
+0092: 2d 00 61 00 61 00 30 00
+009a: 66 00 32 00 66 00 64 00
+00a2: 62 00 61 00 66 00 63 00
+00aa: 39 00 7d 00 00 00 00 00
+"#);
+
+        // Truncated data is dumped without annotations
+        let dump = format!("{}", DescriptorSetDump(&DATA[..0x32]));
+        assert!(dump.ends_with("001c: 03 00                        wDescriptorType: MS_OS_20_FEATURE_COMPATBLE_ID
+001e: 57 49 4e 55 53 42 00 00      CompatibleID: \"WINUSB\\x00\\x00\"
+0026: 00 00 00 00 00 00 00 00      SubCompatibleID: \"\\x00\\x00\\x00\\x00\\x00\\x00\\x00\\x00\"
+002e: 84 00 04 00
+"), "{}", dump);
+    }
+
+    #[test]
+    fn capability_dump() {
+        const CAPABILITIES: Capabilities = Capabilities {
+            infos: &[crate::os_20::CapabilityInfo { descriptors: &EXAMPLE_SET, alt_enum_cmd: 0 }],
+        };
+        const DATA: [u8; CAPABILITIES.data_len()] = CAPABILITIES.descriptor_data();
+        let dump = format!("{}", CapabilityDump(&DATA));
+        assert_eq!(dump, "\
+0000: 00                       bReserved
+0001: df 60 dd d8 89 45 c7 4c  PlatformCapabilityUUID: D8DD60DF-4589-4CC7-9CD2-659D9E648A9F (MS OS 2.0)
+0009: 9c d2 65 9d 9e 64 8a 9f
+0011: 00 00 03 06              dwWindowsVersion: 0x06030000 (WinBlue)
+0015: b2 00                    wMSOSDescriptorSetTotalLength: 178
+0017: 01                       bMS_VendorCode: 1
+0018: 00                       bAltEnumCode: 0
+");
+        let dump = format!("{}", CapabilityDump(&DATA[..0x13]));
+        assert!(dump.ends_with("0011: 00 00\n"), "{}", dump);
+    }
+}
@@ -0,0 +1,676 @@
+#[cfg(any(feature = "serde", feature = "std"))]
+use alloc::string::String;
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::windows_version::WindowsVersion;
+
+use super::{DescriptorType, Error, FeatureFields, PropertyDataType};
+
+mod builder;
+mod decode;
+mod diff;
+#[cfg(test)]
+mod proptests;
+#[cfg(feature = "serde")]
+mod serde_repr;
+
+pub use builder::{ConfigurationBuilder, DescriptorSetBuilder, FunctionBuilder};
+pub use decode::{CapabilitySetInfo, DecodeError};
+pub use diff::{Change, Scope};
+
+/// Owned version of [`super::Capabilities`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Capabilities {
+    /// Capability information for each MS OS 2.0 descriptor set
+    pub infos: Vec<CapabilityInfo>,
+}
+
+/// Owned version of [`super::CapabilityInfo`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CapabilityInfo {
+    /// MS OS 2.0 descriptor set for this capability
+    pub descriptors: DescriptorSet,
+    /// bAltEnumCode, non-zero value indicates that device may return non-default USB descriptors
+    pub alt_enum_cmd: u8,
+}
+
+/// Owned version of [`super::DescriptorSet`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DescriptorSet {
+    /// Minimum Windows version for which descriptor set applies
+    pub version: WindowsVersion,
+    /// Features that apply to the whole device regardless of its configuration
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub features: Vec<FeatureDescriptor>,
+    /// Configuration subsets
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub configurations: Vec<ConfigurationSubset>,
+}
+
+/// Owned version of [`super::ConfigurationSubset`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConfigurationSubset {
+    /// bConfigurationValue
+    pub configuration: u8,
+    /// Features that apply to this USB device configuration
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub features: Vec<FeatureDescriptor>,
+    /// Subsets for specific device functions
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub functions: Vec<FunctionSubset>,
+}
+
+/// Owned version of [`super::FunctionSubset`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FunctionSubset {
+    /// Interface number for the first interface of the function to which this subset applies
+    pub first_interface: u8,
+    /// Features that apply to to specific USB function (group of interfaces) within a configuration
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub features: Vec<FeatureDescriptor>,
+}
+
+/// Owned version of [`super::FeatureDescriptor`]
+///
+/// With `serde` feature, values are (de)serialized in human-readable form where this is lossless,
+/// e.g. compatible IDs, registry property names and string data as strings, model ID as a GUID.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "serde_repr::FeatureRepr", try_from = "serde_repr::FeatureRepr"))]
+#[allow(missing_docs)]
+pub enum FeatureDescriptor {
+    /// See [`super::FeatureDescriptor::CompatibleId`]
+    CompatibleId { id: [u8; 8], sub_id: [u8; 8] },
+    /// See [`super::FeatureDescriptor::RegistryProperty`]
+    RegistryProperty { data_type: PropertyDataType, name: Vec<u16>, data: Vec<u8> },
+    /// See [`super::FeatureDescriptor::ResumeTime`]
+    ResumeTime { recovery: u8, signaling: u8 },
+    /// See [`super::FeatureDescriptor::ModelId`]
+    ModelId { id: [u8; 16] },
+    /// See [`super::FeatureDescriptor::CcgpDevice`]
+    CcgpDevice,
+    /// See [`super::FeatureDescriptor::VendorRevision`]
+    VendorRevision { revision: u16 },
+}
+
+impl core::fmt::Debug for FeatureDescriptor {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let fields = match self {
+            Self::CompatibleId { id, sub_id } => FeatureFields::CompatibleId { id, sub_id },
+            Self::RegistryProperty { data_type, name, data } => {
+                FeatureFields::RegistryProperty { data_type: *data_type, name, data }
+            },
+            Self::ResumeTime { recovery, signaling } => {
+                FeatureFields::ResumeTime { recovery: *recovery, signaling: *signaling }
+            },
+            Self::ModelId { id } => FeatureFields::ModelId { id },
+            Self::CcgpDevice => FeatureFields::CcgpDevice,
+            Self::VendorRevision { revision } => FeatureFields::VendorRevision { revision: *revision },
+        };
+        fields.fmt(f)
+    }
+}
+
+impl FeatureDescriptor {
+    /// Get total size of descriptor
+    pub fn size(&self) -> usize {
+        self.total_len()
+    }
+
+    fn total_len(&self) -> usize {
+        match self {
+            Self::CompatibleId { .. } => 2 + 2 + 8 + 8,
+            Self::RegistryProperty { name, data, .. } => {
+                2 + 2 + 2 + 2 + 2 + 2 * name.len() + data.len()
+            },
+            Self::ResumeTime { .. } => 2 + 2 + 1 + 1,
+            Self::ModelId { .. } => 2 + 2 + 16,
+            Self::CcgpDevice => 2 + 2,
+            Self::VendorRevision { .. } => 2 + 2 + 2,
+        }
+    }
+
+    fn slice_total_len(items: &[Self]) -> usize {
+        items.iter().map(Self::total_len).sum()
+    }
+
+    fn descriptor_type(&self) -> DescriptorType {
+        match self {
+            Self::CompatibleId { .. } => DescriptorType::FeatureCompatbleId,
+            Self::RegistryProperty { .. } => DescriptorType::FeatureRegProperty,
+            Self::ResumeTime { .. } => DescriptorType::FeatureMinResumeTime,
+            Self::ModelId { .. } => DescriptorType::FeatureModelId,
+            Self::CcgpDevice => DescriptorType::FeatureCcgpDevice,
+            Self::VendorRevision { .. } => DescriptorType::FeatureVendorRevision,
+        }
+    }
+}
+
+impl FunctionSubset {
+    /// Get total size of descriptor
+    pub fn size(&self) -> usize {
+        self.total_len()
+    }
+
+    fn total_len(&self) -> usize {
+        super::FunctionSubset::HEADER_SIZE + FeatureDescriptor::slice_total_len(&self.features)
+    }
+}
+
+impl ConfigurationSubset {
+    /// Get total size of descriptor
+    pub fn size(&self) -> usize {
+        self.total_len()
+    }
+
+    fn total_len(&self) -> usize {
+        super::ConfigurationSubset::HEADER_SIZE
+            + FeatureDescriptor::slice_total_len(&self.features)
+            + self.functions.iter().map(FunctionSubset::total_len).sum::<usize>()
+    }
+}
+
+impl DescriptorSet {
+    /// Get total size of descriptor
+    pub fn size(&self) -> usize {
+        self.total_len()
+    }
+
+    fn total_len(&self) -> usize {
+        super::DescriptorSet::HEADER_SIZE
+            + FeatureDescriptor::slice_total_len(&self.features)
+            + self.configurations.iter().map(ConfigurationSubset::total_len).sum::<usize>()
+    }
+
+    /// Write descriptor to a buffer
+    ///
+    /// Uses the same encoder as [`super::DescriptorSet::write_to`].
+    pub fn write_to(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        if !self.version.is_allowed() {
+            return Err(Error::InvalidVersion);
+        }
+        self.check_len()?;
+        if buf.len() < self.size() {
+            return Err(Error::BufferTooSmall);
+        }
+
+        let mut pos = 0;
+        descriptor_set!(buf, pos, self);
+        Ok(pos)
+    }
+
+    /// Check that wTotalLength fits in u16, see [`super::DescriptorSet::check_len`]
+    pub(crate) fn check_len(&self) -> Result<(), Error> {
+        if self.total_len() > u16::MAX as usize {
+            return Err(Error::TooLarge);
+        }
+        Ok(())
+    }
+
+    /// Get encoded descriptor
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut buf = vec![0; self.size()];
+        self.write_to(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Decode descriptor set from raw data
+    ///
+    /// Data must contain exactly one descriptor set, with subsets and features in the order
+    /// required by the specification, so that [`Self::to_bytes`] gives back the same data
+    /// (except for reserved fields).
+    pub fn from_bytes(data: &[u8]) -> Result<Self, DecodeError> {
+        decode::descriptor_set(data)
+    }
+
+    /// Compare with a newer version of the descriptor set
+    ///
+    /// Subsets are matched by their configuration value/first interface and features by their
+    /// type (registry properties by name), so changes are reported per subset regardless of
+    /// byte offsets. To compare const structures or raw data convert them first, e.g.
+    /// `DescriptorSet::from(&SET).diff(&DescriptorSet::from_bytes(data)?)`.
+    pub fn diff(&self, new: &DescriptorSet) -> Vec<Change> {
+        diff::descriptor_set(self, new)
+    }
+}
+
+impl Capabilities {
+    /// Size of data as passed to BOS writer's `capability` method
+    pub fn data_len(&self) -> usize {
+        super::Capabilities::HEADER_SIZE - 3 + self.infos.len() * super::CapabilityInfo::TOTAL_LEN
+    }
+
+    /// Write capability data to a buffer
+    ///
+    /// Uses the same encoder as [`super::Capabilities::write_data_to`].
+    pub fn write_data_to(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        if self.infos.iter().any(|info| !info.descriptors.version.is_allowed()) {
+            return Err(Error::InvalidVersion);
+        }
+        capabilities_check_len!(self)?;
+        if buf.len() < self.data_len() {
+            return Err(Error::BufferTooSmall);
+        }
+
+        let mut pos = 0;
+        capabilities_data!(buf, pos, self);
+        Ok(pos)
+    }
+
+    /// Get encoded capability data
+    pub fn data_to_vec(&self) -> Result<Vec<u8>, Error> {
+        let mut buf = vec![0; self.data_len()];
+        self.write_data_to(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Decode descriptor set information from capability data (without the first 3 bytes)
+    ///
+    /// Unlike [`Self::from_data`] this does not make any assumptions about vendor codes.
+    pub fn parse_data(data: &[u8]) -> Result<Vec<CapabilitySetInfo>, DecodeError> {
+        decode::capability_data(data)
+    }
+
+    /// Decode capabilities from capability data and data of all descriptor sets
+    ///
+    /// Descriptor sets must match their information in capability data and use vendor codes
+    /// in the same way as [`super::Capabilities`], i.e. be in the order of their vendor codes.
+    pub fn from_data(data: &[u8], descriptor_sets: &[&[u8]]) -> Result<Self, DecodeError> {
+        decode::capabilities(data, descriptor_sets)
+    }
+}
+
+impl From<&super::FeatureDescriptor> for FeatureDescriptor {
+    fn from(feature: &super::FeatureDescriptor) -> Self {
+        match *feature {
+            super::FeatureDescriptor::CompatibleId { id, sub_id } => Self::CompatibleId { id: *id, sub_id: *sub_id },
+            super::FeatureDescriptor::RegistryProperty { data_type, name, data } => Self::RegistryProperty {
+                data_type,
+                name: name.to_vec(),
+                data: data.to_vec(),
+            },
+            super::FeatureDescriptor::ResumeTime { recovery, signaling } => Self::ResumeTime { recovery, signaling },
+            super::FeatureDescriptor::ModelId { id } => Self::ModelId { id: *id },
+            super::FeatureDescriptor::CcgpDevice => Self::CcgpDevice,
+            super::FeatureDescriptor::VendorRevision { revision } => Self::VendorRevision { revision },
+        }
+    }
+}
+
+impl From<&super::FunctionSubset> for FunctionSubset {
+    fn from(function: &super::FunctionSubset) -> Self {
+        Self {
+            first_interface: function.first_interface,
+            features: function.features.iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<&super::ConfigurationSubset> for ConfigurationSubset {
+    fn from(config: &super::ConfigurationSubset) -> Self {
+        Self {
+            configuration: config.configuration,
+            features: config.features.iter().map(Into::into).collect(),
+            functions: config.functions.iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<&super::DescriptorSet> for DescriptorSet {
+    fn from(set: &super::DescriptorSet) -> Self {
+        Self {
+            version: set.version,
+            features: set.features.iter().map(Into::into).collect(),
+            configurations: set.configurations.iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<&super::Capabilities> for Capabilities {
+    fn from(capabilities: &super::Capabilities) -> Self {
+        Self {
+            infos: capabilities.infos.iter()
+                .map(|info| CapabilityInfo {
+                    descriptors: info.descriptors.into(),
+                    alt_enum_cmd: info.alt_enum_cmd,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl FeatureDescriptor {
+    /// Convert into borrowed structure, leaking the allocated data
+    pub fn leak(self) -> super::FeatureDescriptor {
+        match self {
+            Self::CompatibleId { id, sub_id } => super::FeatureDescriptor::CompatibleId {
+                id: Box::leak(Box::new(id)),
+                sub_id: Box::leak(Box::new(sub_id)),
+            },
+            Self::RegistryProperty { data_type, name, data } => super::FeatureDescriptor::RegistryProperty {
+                data_type,
+                name: name.leak(),
+                data: data.leak(),
+            },
+            Self::ResumeTime { recovery, signaling } => super::FeatureDescriptor::ResumeTime { recovery, signaling },
+            Self::ModelId { id } => super::FeatureDescriptor::ModelId { id: Box::leak(Box::new(id)) },
+            Self::CcgpDevice => super::FeatureDescriptor::CcgpDevice,
+            Self::VendorRevision { revision } => super::FeatureDescriptor::VendorRevision { revision },
+        }
+    }
+
+    fn leak_all(features: Vec<Self>) -> &'static [super::FeatureDescriptor] {
+        features.into_iter().map(Self::leak).collect::<Vec<_>>().leak()
+    }
+}
+
+impl FunctionSubset {
+    /// Convert into borrowed structure, leaking the allocated data
+    pub fn leak(self) -> super::FunctionSubset {
+        super::FunctionSubset {
+            first_interface: self.first_interface,
+            features: FeatureDescriptor::leak_all(self.features),
+        }
+    }
+}
+
+impl ConfigurationSubset {
+    /// Convert into borrowed structure, leaking the allocated data
+    pub fn leak(self) -> super::ConfigurationSubset {
+        super::ConfigurationSubset {
+            configuration: self.configuration,
+            features: FeatureDescriptor::leak_all(self.features),
+            functions: self.functions.into_iter().map(FunctionSubset::leak).collect::<Vec<_>>().leak(),
+        }
+    }
+}
+
+impl DescriptorSet {
+    /// Convert into borrowed structures, leaking the allocated data
+    ///
+    /// Allows to use descriptor sets created in runtime where `&'static` structures are required,
+    /// e.g. in [`super::Capabilities`]. The memory is never freed, so this should only be used
+    /// for descriptor sets that live until the end of the program.
+    pub fn leak(self) -> &'static super::DescriptorSet {
+        Box::leak(Box::new(super::DescriptorSet {
+            version: self.version,
+            features: FeatureDescriptor::leak_all(self.features),
+            configurations: self.configurations.into_iter().map(ConfigurationSubset::leak).collect::<Vec<_>>().leak(),
+        }))
+    }
+}
+
+impl Capabilities {
+    /// Convert into borrowed structures, leaking the allocated data, see [`DescriptorSet::leak`]
+    pub fn leak(self) -> super::Capabilities {
+        super::Capabilities {
+            infos: self.infos.into_iter()
+                .map(|info| super::CapabilityInfo {
+                    descriptors: info.descriptors.leak(),
+                    alt_enum_cmd: info.alt_enum_cmd,
+                })
+                .collect::<Vec<_>>()
+                .leak(),
+        }
+    }
+}
+
+/// Get string from UTF-16 data with a single NULL terminator
+#[cfg(any(feature = "serde", feature = "std"))]
+pub(crate) fn utf16_null_str(data: &[u16]) -> Option<String> {
+    match data.split_last() {
+        Some((0, text)) if !text.contains(&0) => String::from_utf16(text).ok(),
+        _ => None,
+    }
+}
+
+/// Split UTF-16 little-endian data into NULL-terminated strings
+///
+/// Returns `None` if data is not a sequence of valid NULL-terminated strings.
+#[cfg(any(feature = "serde", feature = "std"))]
+pub(crate) fn utf16_null_strings(data: &[u8]) -> Option<Vec<String>> {
+    if data.len() % 2 != 0 {
+        return None;
+    }
+    let units: Vec<u16> = data.chunks_exact(2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .collect();
+    units.split_inclusive(|&u| u == 0)
+        .map(|s| match s.split_last() {
+            Some((0, s)) => String::from_utf16(s).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Encode strings as NULL-terminated UTF-16 little-endian data
+#[cfg(any(feature = "serde", feature = "std"))]
+pub(crate) fn utf16_null_bytes<'a>(strings: impl IntoIterator<Item = &'a str>) -> Vec<u8> {
+    strings.into_iter()
+        .flat_map(|s| s.encode_utf16().chain([0]))
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os_20::tests::EXAMPLE_SET;
+    use crate::os_20::FeaturePath;
+
+    #[test]
+    fn encode_same_as_borrowed() {
+        const DESC: [u8; EXAMPLE_SET.size()] = EXAMPLE_SET.descriptor();
+        let set = DescriptorSet::from(&EXAMPLE_SET);
+        assert_eq!(set.size(), EXAMPLE_SET.size());
+        assert_eq!(set.to_bytes().unwrap(), DESC);
+    }
+
+    #[test]
+    fn debug_same_as_borrowed() {
+        let set = DescriptorSet::from(&EXAMPLE_SET);
+        assert_eq!(format!("{:?}", set), format!("{:?}", EXAMPLE_SET));
+        assert_eq!(format!("{:#?}", set), format!("{:#?}", EXAMPLE_SET));
+    }
+
+    #[test]
+    fn encode_too_large() {
+        let feature = |len| FeatureDescriptor::RegistryProperty {
+            data_type: PropertyDataType::RegBinary,
+            name: "Data\0".encode_utf16().collect(),
+            data: vec![0; len],
+        };
+        let mut set = DescriptorSet { version: WindowsVersion::MINIMAL, features: vec![feature(70000)], configurations: vec![] };
+        assert_eq!(set.size(), 10 + 10 + 10 + 70000);
+        assert_eq!(set.to_bytes(), Err(Error::TooLarge));
+        let caps = Capabilities { infos: vec![CapabilityInfo { descriptors: set.clone(), alt_enum_cmd: 0 }] };
+        assert_eq!(caps.data_to_vec(), Err(Error::TooLarge));
+
+        // Largest possible descriptor set
+        set.features[0] = feature(usize::from(u16::MAX) - 30);
+        let data = set.to_bytes().unwrap();
+        assert_eq!(data[8..10], [0xff, 0xff]);
+        assert_eq!(DescriptorSet::from_bytes(&data), Ok(set));
+
+        let info = CapabilityInfo { descriptors: DescriptorSet::from(&EXAMPLE_SET), alt_enum_cmd: 0 };
+        let mut caps = Capabilities { infos: vec![info; 29] };
+        assert!(caps.data_to_vec().is_ok());
+        caps.infos.push(caps.infos[0].clone());
+        assert_eq!(caps.data_to_vec(), Err(Error::TooLarge));
+    }
+
+    #[test]
+    fn decode_round_trip() {
+        const DESC: [u8; EXAMPLE_SET.size()] = EXAMPLE_SET.descriptor();
+        let set = DescriptorSet::from_bytes(&DESC).unwrap();
+        assert_eq!(set, DescriptorSet::from(&EXAMPLE_SET));
+        assert_eq!(set.to_bytes().unwrap(), DESC);
+    }
+
+    #[test]
+    fn structural_diff() {
+        const DESC: [u8; EXAMPLE_SET.size()] = EXAMPLE_SET.descriptor();
+        let old = DescriptorSet::from(&EXAMPLE_SET);
+        assert_eq!(old.diff(&DescriptorSet::from_bytes(&DESC).unwrap()), []);
+
+        let mut new = old.clone();
+        new.version = WindowsVersion::Win10;
+        new.features.push(FeatureDescriptor::CcgpDevice);
+        let function = &mut new.configurations[0].functions[0];
+        let compatible_id = function.features.remove(0);
+        let FeatureDescriptor::RegistryProperty { data, .. } = &mut function.features[0] else { unreachable!() };
+        data[2] = b'0';
+        let changed = function.features[0].clone();
+        new.configurations[0].functions.insert(0, FunctionSubset { first_interface: 0, features: vec![] });
+        new.configurations.push(ConfigurationSubset {
+            configuration: 1,
+            features: vec![FeatureDescriptor::VendorRevision { revision: 1 }],
+            functions: vec![],
+        });
+
+        let function = Scope::Function { configuration: 0, first_interface: 1 };
+        let changes = old.diff(&new);
+        assert_eq!(changes, [
+            Change::Version { old: EXAMPLE_SET.version, new: WindowsVersion::Win10 },
+            Change::FeatureAdded { scope: Scope::Device, feature: FeatureDescriptor::CcgpDevice },
+            Change::FeatureRemoved { scope: function, feature: compatible_id },
+            Change::FeatureChanged {
+                scope: function,
+                old: old.configurations[0].functions[0].features[1].clone(),
+                new: changed,
+            },
+            Change::SubsetAdded(Scope::Function { configuration: 0, first_interface: 0 }),
+            Change::SubsetAdded(Scope::Configuration(1)),
+            Change::FeatureAdded {
+                scope: Scope::Configuration(1),
+                feature: FeatureDescriptor::VendorRevision { revision: 1 },
+            },
+        ]);
+        assert_eq!(format!("{}", changes[2]),
+            "- configuration 0 interface 1: CompatibleId { id: [87, 73, 78, 85, 83, 66, 0, 0], sub_id: [0, 0, 0, 0, 0, 0, 0, 0] }");
+
+        // Only order of features changed
+        let mut new = old.clone();
+        new.configurations[0].functions[0].features.reverse();
+        assert_eq!(old.diff(&new), [Change::Reordered(function)]);
+        assert_eq!(format!("{}", old.diff(&new)[0]), "~ configuration 0 interface 1: order changed");
+    }
+
+    #[test]
+    fn decode_errors() {
+        const DESC: [u8; EXAMPLE_SET.size()] = EXAMPLE_SET.descriptor();
+        assert_eq!(DescriptorSet::from_bytes(&[]), Err(DecodeError::UnexpectedEnd { offset: 0 }));
+        assert_eq!(DescriptorSet::from_bytes(&DESC[..DESC.len() - 1]), Err(DecodeError::UnexpectedEnd { offset: 0 }));
+        assert_eq!(DescriptorSet::from_bytes(&[&DESC[..], &[0]].concat()),
+            Err(DecodeError::TrailingData { offset: DESC.len() }));
+
+        // Versions newer than the defined ones are kept as raw values
+        let mut data = DESC;
+        data[4..8].copy_from_slice(&0x0A00_00FF_u32.to_le_bytes());
+        let set = DescriptorSet::from_bytes(&data).unwrap();
+        assert_eq!(set.version, WindowsVersion::Other(0x0A00_00FF));
+        assert_eq!(set.to_bytes().unwrap(), data);
+
+        // Second function subset header in place of a feature of the first function subset
+        let mut data = DESC;
+        let offset = EXAMPLE_SET.feature_offset(FeaturePath::Function { configuration: 0, function: 0, feature: 0 });
+        data[offset + 2] = 0x01;
+        assert_eq!(DescriptorSet::from_bytes(&data),
+            Err(DecodeError::UnexpectedDescriptor { offset, descriptor_type: 0x01 }));
+
+        // Feature length exceeding function subset
+        let mut data = DESC;
+        data[offset] += 1;
+        assert_eq!(DescriptorSet::from_bytes(&data), Err(DecodeError::InvalidLength { offset }));
+
+        let mut data = DESC;
+        data[offset + 2] = 0x04;
+        data[offset + 4..offset + 6].copy_from_slice(&[0x09, 0]);
+        assert_eq!(DescriptorSet::from_bytes(&data),
+            Err(DecodeError::UnknownPropertyDataType { offset, data_type: 0x09 }));
+
+        // Registry property data shorter than the descriptor
+        let mut data = DESC;
+        let offset = EXAMPLE_SET.feature_offset(FeaturePath::Function { configuration: 0, function: 0, feature: 1 });
+        data[offset + 50] -= 2;
+        assert_eq!(DescriptorSet::from_bytes(&data), Err(DecodeError::InvalidLength { offset }));
+
+        // Any truncated or corrupted data must give an error, not a panic
+        for len in 0..DESC.len() {
+            assert!(DescriptorSet::from_bytes(&DESC[..len]).is_err());
+        }
+        for i in 0..DESC.len() {
+            let mut data = DESC;
+            data[i] ^= 0xff;
+            let _ = DescriptorSet::from_bytes(&data);
+        }
+    }
+
+    #[test]
+    fn builder() {
+        let FeatureDescriptor::RegistryProperty { data_type, name, data } =
+            (&EXAMPLE_SET.configurations[0].functions[0].features[1]).into() else { unreachable!() };
+        let builder = DescriptorSetBuilder::new(WindowsVersion::MINIMAL)
+            .configuration(0, |c| c.function(1, |f| f
+                .feature(FeatureDescriptor::CompatibleId { id: *b"WINUSB\0\0", sub_id: [0; 8] })
+                .feature(FeatureDescriptor::RegistryProperty { data_type, name, data })));
+        const DESC: [u8; EXAMPLE_SET.size()] = EXAMPLE_SET.descriptor();
+        assert_eq!(builder.to_bytes().unwrap(), DESC);
+        assert_eq!(builder.build(), DescriptorSet::from(&EXAMPLE_SET));
+
+        // Borrowed features are converted
+        let set = DescriptorSetBuilder::new(WindowsVersion::MINIMAL)
+            .feature(&crate::os_20::FeatureDescriptor::CcgpDevice)
+            .configuration(1, |c| c.feature(&crate::os_20::FeatureDescriptor::VendorRevision { revision: 2 }))
+            .build();
+        assert_eq!(set.features, [FeatureDescriptor::CcgpDevice]);
+        assert_eq!(set.configurations[0].features, [FeatureDescriptor::VendorRevision { revision: 2 }]);
+        assert_eq!(set.configurations[0].functions, []);
+    }
+
+    #[test]
+    fn leak_to_borrowed() {
+        let set = DescriptorSet::from(&EXAMPLE_SET).leak();
+        assert_eq!(set, &EXAMPLE_SET);
+        const DESC: [u8; EXAMPLE_SET.size()] = EXAMPLE_SET.descriptor();
+        assert_eq!(set.descriptor::<{ EXAMPLE_SET.size() }>(), DESC);
+    }
+
+    #[test]
+    fn capabilities_same_as_borrowed() {
+        const CAPABILITIES: crate::os_20::Capabilities = crate::os_20::Capabilities {
+            infos: &[
+                crate::os_20::CapabilityInfo { descriptors: &EXAMPLE_SET, alt_enum_cmd: 0 },
+                crate::os_20::CapabilityInfo { descriptors: &EXAMPLE_SET, alt_enum_cmd: 3 },
+            ],
+        };
+        const DATA: [u8; CAPABILITIES.data_len()] = CAPABILITIES.descriptor_data();
+        const DESC: [u8; EXAMPLE_SET.size()] = EXAMPLE_SET.descriptor();
+        let caps = Capabilities::from(&CAPABILITIES);
+        assert_eq!(caps.data_len(), DATA.len());
+        assert_eq!(caps.data_to_vec().unwrap(), DATA);
+        assert_eq!(Capabilities::from_data(&DATA, &[&DESC, &DESC]).unwrap(), caps);
+        assert_eq!(Capabilities::from_data(&DATA, &[&DESC]), Err(DecodeError::CapabilityMismatch { index: 1 }));
+        assert_eq!(Capabilities::parse_data(&DATA).unwrap()[1], CapabilitySetInfo {
+            version: EXAMPLE_SET.version,
+            total_len: DESC.len() as u16,
+            vendor_code: 2,
+            alt_enum_cmd: 3,
+        });
+
+        // Versions newer than the defined ones are kept as raw values
+        let mut data = DATA;
+        data[17..21].copy_from_slice(&0x0A00_000Fu32.to_le_bytes());
+        let info = Capabilities::parse_data(&data).unwrap()[0];
+        assert_eq!(info.version, WindowsVersion::Other(0x0A00_000F));
+        assert_eq!(Capabilities::from_data(&data, &[&DESC, &DESC]), Err(DecodeError::CapabilityMismatch { index: 0 }));
+        assert_eq!(Capabilities::parse_data(&DATA[1..]), Err(DecodeError::InvalidCapability));
+    }
+}
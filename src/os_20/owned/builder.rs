@@ -0,0 +1,118 @@
+//! Builder for descriptor sets constructed in runtime
+
+use alloc::vec::Vec;
+
+use crate::os_20::Error;
+use crate::windows_version::WindowsVersion;
+
+use super::{ConfigurationSubset, DescriptorSet, FeatureDescriptor, FunctionSubset};
+
+/// Builder for owned [`DescriptorSet`]
+///
+/// Features and subsets are added in the order of calls, e.g. for a composite device with
+/// WinUSB on interface 1:
+///
+/// ```rust
+/// use usbd_microsoft_os::os_20::owned::{DescriptorSetBuilder, FeatureDescriptor};
+/// use usbd_microsoft_os::WindowsVersion;
+///
+/// let bytes = DescriptorSetBuilder::new(WindowsVersion::MINIMAL)
+///     .feature(FeatureDescriptor::VendorRevision { revision: 1 })
+///     .configuration(0, |c| c.function(1, |f| f.feature(FeatureDescriptor::CompatibleId {
+///         id: *b"WINUSB\0\0",
+///         sub_id: [0; 8],
+///     })))
+///     .to_bytes()
+///     .unwrap();
+/// assert_eq!(bytes.len(), 10 + 6 + 8 + 8 + 20);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DescriptorSetBuilder {
+    set: DescriptorSet,
+}
+
+/// Builder for owned [`ConfigurationSubset`], see [`DescriptorSetBuilder::configuration`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigurationBuilder {
+    subset: ConfigurationSubset,
+}
+
+/// Builder for owned [`FunctionSubset`], see [`ConfigurationBuilder::function`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionBuilder {
+    subset: FunctionSubset,
+}
+
+impl DescriptorSetBuilder {
+    /// Start an empty descriptor set for given minimum Windows version
+    pub fn new(version: WindowsVersion) -> Self {
+        Self {
+            set: DescriptorSet { version, features: Vec::new(), configurations: Vec::new() },
+        }
+    }
+
+    /// Add feature that applies to the whole device
+    ///
+    /// Accepts owned features as well as references to borrowed ones.
+    pub fn feature(mut self, feature: impl Into<FeatureDescriptor>) -> Self {
+        self.set.features.push(feature.into());
+        self
+    }
+
+    /// Add configuration subset for bConfigurationValue `configuration`, filled by `build`
+    pub fn configuration(mut self, configuration: u8, build: impl FnOnce(ConfigurationBuilder) -> ConfigurationBuilder) -> Self {
+        let builder = ConfigurationBuilder {
+            subset: ConfigurationSubset { configuration, features: Vec::new(), functions: Vec::new() },
+        };
+        self.set.configurations.push(build(builder).subset);
+        self
+    }
+
+    /// Get the built descriptor set
+    pub fn build(self) -> DescriptorSet {
+        self.set
+    }
+
+    /// Get encoded descriptor, see [`DescriptorSet::to_bytes`]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        self.set.to_bytes()
+    }
+}
+
+impl ConfigurationBuilder {
+    /// Add feature that applies to this configuration
+    pub fn feature(mut self, feature: impl Into<FeatureDescriptor>) -> Self {
+        self.subset.features.push(feature.into());
+        self
+    }
+
+    /// Add function subset starting at interface `first_interface`, filled by `build`
+    pub fn function(mut self, first_interface: u8, build: impl FnOnce(FunctionBuilder) -> FunctionBuilder) -> Self {
+        let builder = FunctionBuilder {
+            subset: FunctionSubset { first_interface, features: Vec::new() },
+        };
+        self.subset.functions.push(build(builder).subset);
+        self
+    }
+}
+
+impl FunctionBuilder {
+    /// Add feature that applies to this function
+    pub fn feature(mut self, feature: impl Into<FeatureDescriptor>) -> Self {
+        self.subset.features.push(feature.into());
+        self
+    }
+}
+
+impl From<DescriptorSetBuilder> for DescriptorSet {
+    fn from(builder: DescriptorSetBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl From<DescriptorSet> for DescriptorSetBuilder {
+    /// Continue building from an existing descriptor set
+    fn from(set: DescriptorSet) -> Self {
+        Self { set }
+    }
+}
@@ -0,0 +1,344 @@
+//! Decoding of raw descriptor data into owned structures
+
+// Decoded data is untrusted (e.g. captured from a device), so the decoder must not panic
+#![deny(clippy::indexing_slicing, clippy::arithmetic_side_effects, clippy::panic, clippy::unwrap_used, clippy::expect_used)]
+
+use alloc::vec::Vec;
+
+use crate::os_20::{DescriptorType, PropertyDataType};
+use crate::windows_version::WindowsVersion;
+
+use super::{Capabilities, CapabilityInfo, ConfigurationSubset, DescriptorSet, FeatureDescriptor, FunctionSubset};
+
+/// Errors when decoding raw descriptor data
+///
+/// Offsets are relative to the beginning of the decoded data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DecodeError {
+    /// Data ends in the middle of a descriptor
+    UnexpectedEnd {
+        /// Offset of the descriptor
+        offset: usize,
+    },
+    /// Descriptor length is invalid for its type or exceeds its parent
+    InvalidLength {
+        /// Offset of the descriptor
+        offset: usize,
+    },
+    /// Descriptor type is unknown or not allowed at this position
+    UnexpectedDescriptor {
+        /// Offset of the descriptor
+        offset: usize,
+        /// wDescriptorType
+        descriptor_type: u16,
+    },
+    /// Registry property type is not one of [`PropertyDataType`] values
+    UnknownPropertyDataType {
+        /// Offset of the descriptor
+        offset: usize,
+        /// wPropertyDataType
+        data_type: u16,
+    },
+    /// There is more data after the descriptor set
+    TrailingData {
+        /// Offset of the first byte after the descriptor set
+        offset: usize,
+    },
+    /// Data is not a MS OS 2.0 platform capability
+    InvalidCapability,
+    /// Descriptor set does not match its capability information
+    CapabilityMismatch {
+        /// Index of the descriptor set
+        index: usize,
+    },
+}
+
+impl core::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::UnexpectedEnd { offset } => write!(f, "data ends inside descriptor at offset {}", offset),
+            Self::InvalidLength { offset } => write!(f, "invalid length of descriptor at offset {}", offset),
+            Self::UnexpectedDescriptor { offset, descriptor_type } =>
+                write!(f, "unexpected descriptor type 0x{:02x} at offset {}", descriptor_type, offset),
+            Self::UnknownPropertyDataType { offset, data_type } =>
+                write!(f, "unknown registry property type {} at offset {}", data_type, offset),
+            Self::TrailingData { offset } => write!(f, "trailing data after descriptor set at offset {}", offset),
+            Self::InvalidCapability => f.write_str("not a MS OS 2.0 platform capability"),
+            Self::CapabilityMismatch { index } =>
+                write!(f, "descriptor set {} does not match its capability information", index),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {}
+
+/// Descriptor set information as encoded in platform capability data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CapabilitySetInfo {
+    /// dwWindowsVersion
+    pub version: WindowsVersion,
+    /// wMSOSDescriptorSetTotalLength
+    pub total_len: u16,
+    /// bMS_VendorCode
+    pub vendor_code: u8,
+    /// bAltEnumCode
+    pub alt_enum_cmd: u8,
+}
+
+/// Bounds-checked little-endian reader
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize, offset: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self.pos.checked_add(n).ok_or(DecodeError::UnexpectedEnd { offset })?;
+        let bytes = self.data.get(self.pos..end).ok_or(DecodeError::UnexpectedEnd { offset })?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self, offset: usize) -> Result<[u8; N], DecodeError> {
+        self.bytes(N, offset)?.try_into().map_err(|_| DecodeError::UnexpectedEnd { offset })
+    }
+
+    fn u8(&mut self, offset: usize) -> Result<u8, DecodeError> {
+        let [byte] = self.array(offset)?;
+        Ok(byte)
+    }
+
+    fn u16(&mut self, offset: usize) -> Result<u16, DecodeError> {
+        Ok(u16::from_le_bytes(self.array(offset)?))
+    }
+
+    fn u32(&mut self, offset: usize) -> Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes(self.array(offset)?))
+    }
+
+    /// Read wLength and wDescriptorType of the next descriptor, limited to `end`
+    ///
+    /// Returns the end of the descriptor, its length and raw type.
+    fn header(&mut self, end: usize) -> Result<(usize, usize, u16), DecodeError> {
+        let offset = self.pos;
+        let len = usize::from(self.u16(offset)?);
+        let descriptor_type = self.u16(offset)?;
+        let descriptor_end = offset.checked_add(len)
+            .filter(|&descriptor_end| len >= 4 && descriptor_end <= end)
+            .ok_or(DecodeError::InvalidLength { offset })?;
+        Ok((descriptor_end, len, descriptor_type))
+    }
+
+    /// Get raw type of the next descriptor without advancing
+    fn peek_raw_type(&self) -> Option<u16> {
+        match self.data.get(self.pos..)? {
+            [_, _, low, high, ..] => Some(u16::from_le_bytes([*low, *high])),
+            _ => None,
+        }
+    }
+
+    /// Get type of the next descriptor without advancing
+    fn peek_type(&self) -> Option<DescriptorType> {
+        DescriptorType::from_u16(self.peek_raw_type()?)
+    }
+}
+
+fn is_feature(descriptor_type: Option<DescriptorType>) -> bool {
+    !matches!(
+        descriptor_type,
+        None | Some(DescriptorType::SetHeaderDescriptor
+            | DescriptorType::SubsetHeaderConfiguration
+            | DescriptorType::SubsetHeaderFunction)
+    )
+}
+
+fn feature(r: &mut Reader, end: usize) -> Result<FeatureDescriptor, DecodeError> {
+    let offset = r.pos;
+    let (feature_end, feature_len, descriptor_type) = r.header(end)?;
+    let expect_len = |len: usize| match feature_len == len {
+        true => Ok(()),
+        false => Err(DecodeError::InvalidLength { offset }),
+    };
+
+    let feature = match DescriptorType::from_u16(descriptor_type) {
+        Some(DescriptorType::FeatureCompatbleId) => {
+            expect_len(20)?;
+            FeatureDescriptor::CompatibleId { id: r.array(offset)?, sub_id: r.array(offset)? }
+        },
+        Some(DescriptorType::FeatureRegProperty) => {
+            let raw_type = r.u16(offset)?;
+            let data_type = PropertyDataType::from_u16(raw_type)
+                .ok_or(DecodeError::UnknownPropertyDataType { offset, data_type: raw_type })?;
+            let name_len = usize::from(r.u16(offset)?);
+            if name_len % 2 != 0 {
+                return Err(DecodeError::InvalidLength { offset });
+            }
+            let name = r.bytes(name_len, offset)?
+                .chunks_exact(2)
+                .filter_map(|b| b.try_into().ok())
+                .map(u16::from_le_bytes)
+                .collect();
+            let data_len = usize::from(r.u16(offset)?);
+            let data = r.bytes(data_len, offset)?.to_vec();
+            // Name and data must fill the whole descriptor
+            if r.pos != feature_end {
+                return Err(DecodeError::InvalidLength { offset });
+            }
+            FeatureDescriptor::RegistryProperty { data_type, name, data }
+        },
+        Some(DescriptorType::FeatureMinResumeTime) => {
+            expect_len(6)?;
+            FeatureDescriptor::ResumeTime { recovery: r.u8(offset)?, signaling: r.u8(offset)? }
+        },
+        Some(DescriptorType::FeatureModelId) => {
+            expect_len(20)?;
+            FeatureDescriptor::ModelId { id: r.array(offset)? }
+        },
+        Some(DescriptorType::FeatureCcgpDevice) => {
+            expect_len(4)?;
+            FeatureDescriptor::CcgpDevice
+        },
+        Some(DescriptorType::FeatureVendorRevision) => {
+            expect_len(6)?;
+            FeatureDescriptor::VendorRevision { revision: r.u16(offset)? }
+        },
+        _ => return Err(DecodeError::UnexpectedDescriptor { offset, descriptor_type }),
+    };
+    Ok(feature)
+}
+
+/// Read all feature descriptors until the next subset header or `end`
+fn features(r: &mut Reader, end: usize) -> Result<Vec<FeatureDescriptor>, DecodeError> {
+    let mut features = Vec::new();
+    while r.pos < end && is_feature(r.peek_type()) {
+        features.push(feature(r, end)?);
+    }
+    Ok(features)
+}
+
+/// Error for the next descriptor which is not allowed at current position
+fn unexpected(r: &Reader) -> DecodeError {
+    let offset = r.pos;
+    match r.peek_raw_type() {
+        Some(descriptor_type) => DecodeError::UnexpectedDescriptor { offset, descriptor_type },
+        None => DecodeError::UnexpectedEnd { offset },
+    }
+}
+
+/// Check that next descriptor is of given type
+fn expect_type(r: &Reader, descriptor_type: DescriptorType) -> Result<(), DecodeError> {
+    match r.peek_type() == Some(descriptor_type) {
+        true => Ok(()),
+        false => Err(unexpected(r)),
+    }
+}
+
+/// Read subset header with fixed length of 8 and return its value and end of the subset
+fn subset_header(r: &mut Reader, end: usize) -> Result<(u8, usize), DecodeError> {
+    let offset = r.pos;
+    let (header_end, header_len, _) = r.header(end)?;
+    if header_len != 8 {
+        return Err(DecodeError::InvalidLength { offset });
+    }
+    let value = r.u8(offset)?;
+    let _reserved = r.u8(offset)?;
+    let subset_end = offset.checked_add(usize::from(r.u16(offset)?))
+        .filter(|&subset_end| subset_end >= header_end && subset_end <= end)
+        .ok_or(DecodeError::InvalidLength { offset })?;
+    Ok((value, subset_end))
+}
+
+fn function(r: &mut Reader, end: usize) -> Result<FunctionSubset, DecodeError> {
+    expect_type(r, DescriptorType::SubsetHeaderFunction)?;
+    let (first_interface, end) = subset_header(r, end)?;
+    let features = features(r, end)?;
+    if r.pos != end {
+        // Function subset can only contain feature descriptors
+        return Err(unexpected(r));
+    }
+    Ok(FunctionSubset { first_interface, features })
+}
+
+fn configuration(r: &mut Reader, end: usize) -> Result<ConfigurationSubset, DecodeError> {
+    expect_type(r, DescriptorType::SubsetHeaderConfiguration)?;
+    let (configuration, end) = subset_header(r, end)?;
+    let features = features(r, end)?;
+    let mut functions = Vec::new();
+    while r.pos < end {
+        functions.push(function(r, end)?);
+    }
+    Ok(ConfigurationSubset { configuration, features, functions })
+}
+
+pub(super) fn descriptor_set(data: &[u8]) -> Result<DescriptorSet, DecodeError> {
+    let mut r = Reader { data, pos: 0 };
+    expect_type(&r, DescriptorType::SetHeaderDescriptor)?;
+    let (header_end, header_len, _) = r.header(data.len())?;
+    if header_len != crate::os_20::DescriptorSet::HEADER_SIZE {
+        return Err(DecodeError::InvalidLength { offset: 0 });
+    }
+    let version = WindowsVersion::from_value(r.u32(0)?);
+    let end = usize::from(r.u16(0)?);
+    if end < header_end {
+        return Err(DecodeError::InvalidLength { offset: 0 });
+    } else if end > data.len() {
+        return Err(DecodeError::UnexpectedEnd { offset: 0 });
+    }
+
+    let features = features(&mut r, end)?;
+    let mut configurations = Vec::new();
+    while r.pos < end {
+        configurations.push(configuration(&mut r, end)?);
+    }
+
+    if end != data.len() {
+        return Err(DecodeError::TrailingData { offset: end });
+    }
+    Ok(DescriptorSet { version, features, configurations })
+}
+
+pub(super) fn capability_data(data: &[u8]) -> Result<Vec<CapabilitySetInfo>, DecodeError> {
+    let mut r = Reader { data, pos: 0 };
+    let _reserved = r.u8(0)?;
+    if r.array::<16>(0)? != crate::os_20::Capabilities::CAPABILITY_ID {
+        return Err(DecodeError::InvalidCapability);
+    }
+    if data.len().saturating_sub(r.pos) % crate::os_20::CapabilityInfo::TOTAL_LEN != 0 {
+        return Err(DecodeError::InvalidLength { offset: r.pos });
+    }
+
+    let mut infos = Vec::new();
+    while r.pos < data.len() {
+        let offset = r.pos;
+        infos.push(CapabilitySetInfo {
+            version: WindowsVersion::from_value(r.u32(offset)?),
+            total_len: r.u16(offset)?,
+            vendor_code: r.u8(offset)?,
+            alt_enum_cmd: r.u8(offset)?,
+        });
+    }
+    Ok(infos)
+}
+
+pub(super) fn capabilities(data: &[u8], descriptor_sets: &[&[u8]]) -> Result<Capabilities, DecodeError> {
+    let set_infos = capability_data(data)?;
+    if set_infos.len() != descriptor_sets.len() {
+        return Err(DecodeError::CapabilityMismatch { index: set_infos.len().min(descriptor_sets.len()) });
+    }
+
+    let mut infos = Vec::new();
+    for (index, (info, set)) in set_infos.iter().zip(descriptor_sets).enumerate() {
+        let descriptors = descriptor_set(set)?;
+        if info.version != descriptors.version
+            || info.total_len as usize != set.len()
+            || crate::os_20::Capabilities::vendor_code_to_descriptor_set(info.vendor_code) != Some(index)
+        {
+            return Err(DecodeError::CapabilityMismatch { index });
+        }
+        infos.push(CapabilityInfo { descriptors, alt_enum_cmd: info.alt_enum_cmd });
+    }
+    Ok(Capabilities { infos })
+}
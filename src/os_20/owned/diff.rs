@@ -0,0 +1,183 @@
+//! Structural comparison of descriptor sets
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::windows_version::WindowsVersion;
+
+use super::{ConfigurationSubset, DescriptorSet, FeatureDescriptor, FunctionSubset};
+
+/// Part of a descriptor set identified by values that are stable between versions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Scope {
+    /// Descriptor set level (device)
+    Device,
+    /// Configuration subset with given bConfigurationValue
+    Configuration(u8),
+    /// Function subset
+    Function {
+        /// bConfigurationValue of the configuration subset
+        configuration: u8,
+        /// bFirstInterface of the function subset
+        first_interface: u8,
+    },
+}
+
+impl core::fmt::Display for Scope {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Device => f.write_str("device"),
+            Self::Configuration(configuration) => write!(f, "configuration {}", configuration),
+            Self::Function { configuration, first_interface } => {
+                write!(f, "configuration {} interface {}", configuration, first_interface)
+            },
+        }
+    }
+}
+
+/// Difference between two descriptor sets, see [`DescriptorSet::diff`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// Windows version changed
+    Version {
+        /// Old version
+        old: WindowsVersion,
+        /// New version
+        new: WindowsVersion,
+    },
+    /// Configuration or function subset added
+    SubsetAdded(Scope),
+    /// Configuration or function subset removed
+    SubsetRemoved(Scope),
+    /// Feature descriptor added
+    FeatureAdded {
+        /// Where the feature was added
+        scope: Scope,
+        /// New feature
+        feature: FeatureDescriptor,
+    },
+    /// Feature descriptor removed
+    FeatureRemoved {
+        /// Where the feature was removed
+        scope: Scope,
+        /// Old feature
+        feature: FeatureDescriptor,
+    },
+    /// Feature descriptor changed
+    FeatureChanged {
+        /// Where the feature changed
+        scope: Scope,
+        /// Old feature
+        old: FeatureDescriptor,
+        /// New feature
+        new: FeatureDescriptor,
+    },
+    /// Order of features or subsets directly in scope changed
+    Reordered(Scope),
+}
+
+impl core::fmt::Display for Change {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Version { old, new } => write!(f, "~ version: {:?} -> {:?}", old, new),
+            Self::SubsetAdded(scope) => write!(f, "+ {}", scope),
+            Self::SubsetRemoved(scope) => write!(f, "- {}", scope),
+            Self::FeatureAdded { scope, feature } => write!(f, "+ {}: {:?}", scope, feature),
+            Self::FeatureRemoved { scope, feature } => write!(f, "- {}: {:?}", scope, feature),
+            Self::FeatureChanged { scope, old, new } => write!(f, "~ {}: {:?} -> {:?}", scope, old, new),
+            Self::Reordered(scope) => write!(f, "~ {}: order changed", scope),
+        }
+    }
+}
+
+/// Match old and new items, returns pairs in the old order followed by added items
+/// and whether the order of matched items changed
+#[allow(clippy::type_complexity)]
+fn pairs<'a, T>(old: &'a [T], new: &'a [T], same: impl Fn(&T, &T) -> bool) -> (Vec<(Option<&'a T>, Option<&'a T>)>, bool) {
+    let mut used = vec![false; new.len()];
+    let mut order = Vec::new();
+    let mut pairs = Vec::new();
+    for o in old {
+        let found = (0..new.len()).find(|&i| !used[i] && same(o, &new[i]));
+        if let Some(i) = found {
+            used[i] = true;
+            order.push(i);
+        }
+        pairs.push((Some(o), found.map(|i| &new[i])));
+    }
+    pairs.extend(new.iter().zip(used).filter(|(_, used)| !used).map(|(n, _)| (None, Some(n))));
+    (pairs, order.windows(2).any(|w| w[0] > w[1]))
+}
+
+/// Features are identified by type, registry properties by name
+fn same_feature(a: &FeatureDescriptor, b: &FeatureDescriptor) -> bool {
+    match (a, b) {
+        (FeatureDescriptor::RegistryProperty { name: a, .. }, FeatureDescriptor::RegistryProperty { name: b, .. }) => {
+            a == b
+        },
+        _ => core::mem::discriminant(a) == core::mem::discriminant(b),
+    }
+}
+
+fn features(scope: Scope, old: &[FeatureDescriptor], new: &[FeatureDescriptor], changes: &mut Vec<Change>) {
+    let (pairs, reordered) = pairs(old, new, same_feature);
+    for pair in pairs {
+        match pair {
+            (Some(old), Some(new)) if old != new => {
+                changes.push(Change::FeatureChanged { scope, old: old.clone(), new: new.clone() });
+            },
+            (Some(feature), None) => changes.push(Change::FeatureRemoved { scope, feature: feature.clone() }),
+            (None, Some(feature)) => changes.push(Change::FeatureAdded { scope, feature: feature.clone() }),
+            _ => {},
+        }
+    }
+    if reordered {
+        changes.push(Change::Reordered(scope));
+    }
+}
+
+fn subset_change(scope: Scope, old: bool, new: bool, changes: &mut Vec<Change>) {
+    match (old, new) {
+        (true, false) => changes.push(Change::SubsetRemoved(scope)),
+        (false, true) => changes.push(Change::SubsetAdded(scope)),
+        _ => {},
+    }
+}
+
+fn functions(configuration: u8, old: &[FunctionSubset], new: &[FunctionSubset], changes: &mut Vec<Change>) {
+    let (pairs, reordered) = pairs(old, new, |a, b| a.first_interface == b.first_interface);
+    for (old, new) in pairs {
+        let Some(first_interface) = old.or(new).map(|f| f.first_interface) else { continue };
+        let scope = Scope::Function { configuration, first_interface };
+        subset_change(scope, old.is_some(), new.is_some(), changes);
+        features(scope, old.map_or(&[], |f| &f.features), new.map_or(&[], |f| &f.features), changes);
+    }
+    if reordered {
+        changes.push(Change::Reordered(Scope::Configuration(configuration)));
+    }
+}
+
+fn configurations(old: &[ConfigurationSubset], new: &[ConfigurationSubset], changes: &mut Vec<Change>) {
+    let (pairs, reordered) = pairs(old, new, |a, b| a.configuration == b.configuration);
+    for (old, new) in pairs {
+        let Some(configuration) = old.or(new).map(|c| c.configuration) else { continue };
+        let scope = Scope::Configuration(configuration);
+        subset_change(scope, old.is_some(), new.is_some(), changes);
+        features(scope, old.map_or(&[], |c| &c.features), new.map_or(&[], |c| &c.features), changes);
+        functions(configuration, old.map_or(&[], |c| &c.functions), new.map_or(&[], |c| &c.functions), changes);
+    }
+    if reordered {
+        changes.push(Change::Reordered(Scope::Device));
+    }
+}
+
+pub(super) fn descriptor_set(old: &DescriptorSet, new: &DescriptorSet) -> Vec<Change> {
+    let mut changes = Vec::new();
+    if old.version != new.version {
+        changes.push(Change::Version { old: old.version, new: new.version });
+    }
+    features(Scope::Device, &old.features, &new.features, &mut changes);
+    configurations(&old.configurations, &new.configurations, &mut changes);
+    changes
+}
@@ -0,0 +1,141 @@
+//! Property-based tests comparing encoders and the decoder on random descriptor sets
+
+use std::vec::Vec;
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use crate::os_20::PropertyDataType;
+use crate::windows_version::WindowsVersion;
+
+use super::{Capabilities, CapabilityInfo, ConfigurationSubset, DescriptorSet, FeatureDescriptor, FunctionSubset};
+
+/// Size of the buffer for the const encoder, larger than any generated descriptor set
+const BUF_SIZE: usize = 8192;
+
+fn version() -> impl Strategy<Value = WindowsVersion> {
+    prop_oneof![
+        Just(WindowsVersion::WinBlue),
+        Just(WindowsVersion::Win10),
+        Just(WindowsVersion::Win10Rs1),
+        Just(WindowsVersion::Win10Rs5),
+        Just(WindowsVersion::Other(0x0A00_000F)),
+    ]
+}
+
+fn data_type() -> impl Strategy<Value = PropertyDataType> {
+    (1..=7u16).prop_map(|value| PropertyDataType::from_u16(value).unwrap())
+}
+
+fn feature() -> impl Strategy<Value = FeatureDescriptor> {
+    prop_oneof![
+        any::<([u8; 8], [u8; 8])>().prop_map(|(id, sub_id)| FeatureDescriptor::CompatibleId { id, sub_id }),
+        (data_type(), vec(any::<u16>(), 0..16), vec(any::<u8>(), 0..32))
+            .prop_map(|(data_type, name, data)| FeatureDescriptor::RegistryProperty { data_type, name, data }),
+        any::<(u8, u8)>().prop_map(|(recovery, signaling)| FeatureDescriptor::ResumeTime { recovery, signaling }),
+        any::<[u8; 16]>().prop_map(|id| FeatureDescriptor::ModelId { id }),
+        Just(FeatureDescriptor::CcgpDevice),
+        any::<u16>().prop_map(|revision| FeatureDescriptor::VendorRevision { revision }),
+    ]
+}
+
+fn features() -> impl Strategy<Value = Vec<FeatureDescriptor>> {
+    vec(feature(), 0..4)
+}
+
+fn function() -> impl Strategy<Value = FunctionSubset> {
+    (any::<u8>(), features()).prop_map(|(first_interface, features)| FunctionSubset { first_interface, features })
+}
+
+fn configuration() -> impl Strategy<Value = ConfigurationSubset> {
+    (any::<u8>(), features(), vec(function(), 0..4))
+        .prop_map(|(configuration, features, functions)| ConfigurationSubset { configuration, features, functions })
+}
+
+fn descriptor_set() -> impl Strategy<Value = DescriptorSet> {
+    (version(), features(), vec(configuration(), 0..4))
+        .prop_map(|(version, features, configurations)| DescriptorSet { version, features, configurations })
+}
+
+/// Expected feature size computed from the specification
+fn feature_len(feature: &FeatureDescriptor) -> usize {
+    match feature {
+        FeatureDescriptor::CompatibleId { .. } | FeatureDescriptor::ModelId { .. } => 20,
+        FeatureDescriptor::RegistryProperty { name, data, .. } => 10 + 2 * name.len() + data.len(),
+        FeatureDescriptor::ResumeTime { .. } | FeatureDescriptor::VendorRevision { .. } => 6,
+        FeatureDescriptor::CcgpDevice => 4,
+    }
+}
+
+/// Check all length fields in `data` against the structure of `set`
+fn check_lengths(set: &DescriptorSet, data: &[u8]) {
+    let u16_at = |pos: usize| u16::from_le_bytes([data[pos], data[pos + 1]]) as usize;
+    let features = |pos: &mut usize, features: &[FeatureDescriptor]| {
+        for feature in features {
+            assert_eq!(u16_at(*pos), feature_len(feature), "wLength of feature at {}", pos);
+            *pos += feature_len(feature);
+        }
+    };
+
+    assert_eq!(u16_at(0), 10);
+    assert_eq!(u16_at(8), data.len(), "wTotalLength");
+    let mut pos = 10;
+    features(&mut pos, &set.features);
+    for configuration in &set.configurations {
+        let start = pos;
+        assert_eq!(u16_at(pos), 8);
+        pos += 8;
+        features(&mut pos, &configuration.features);
+        for function in &configuration.functions {
+            let start = pos;
+            assert_eq!(u16_at(pos), 8);
+            pos += 8;
+            features(&mut pos, &function.features);
+            assert_eq!(u16_at(start + 6), pos - start, "wSubsetLength of function at {}", start);
+        }
+        assert_eq!(u16_at(start + 6), pos - start, "wTotalLength of configuration at {}", start);
+    }
+    assert_eq!(pos, data.len());
+}
+
+proptest! {
+    #[test]
+    fn encoders_and_decoder_agree(set in descriptor_set()) {
+        let bytes = set.to_bytes().unwrap();
+        prop_assert_eq!(bytes.len(), set.size());
+        check_lengths(&set, &bytes);
+
+        let borrowed = set.clone().leak();
+        prop_assert_eq!(borrowed.size(), set.size());
+        // Same code that runs in const context, array only needs to be large enough
+        let desc: [u8; BUF_SIZE] = borrowed.descriptor();
+        prop_assert_eq!(&desc[..bytes.len()], &bytes[..]);
+        prop_assert!(desc[bytes.len()..].iter().all(|&b| b == 0));
+        let mut buf = [0; BUF_SIZE];
+        prop_assert_eq!(borrowed.write_to(&mut buf), Ok(bytes.len()));
+        prop_assert_eq!(&buf[..bytes.len()], &bytes[..]);
+
+        prop_assert_eq!(DescriptorSet::from(borrowed), set.clone());
+        prop_assert_eq!(DescriptorSet::from_bytes(&bytes), Ok(set));
+    }
+
+    #[test]
+    fn capabilities_agree(sets in vec((descriptor_set(), any::<u8>()), 1..4)) {
+        let caps = Capabilities {
+            infos: sets.into_iter()
+                .map(|(descriptors, alt_enum_cmd)| CapabilityInfo { descriptors, alt_enum_cmd })
+                .collect(),
+        };
+        let data = caps.data_to_vec().unwrap();
+        prop_assert_eq!(data.len(), caps.data_len());
+
+        let borrowed = caps.clone().leak();
+        prop_assert_eq!(borrowed.data_len(), data.len());
+        let desc: [u8; 64] = borrowed.descriptor_data();
+        prop_assert_eq!(&desc[..data.len()], &data[..]);
+
+        let sets: Vec<_> = caps.infos.iter().map(|info| info.descriptors.to_bytes().unwrap()).collect();
+        let sets: Vec<&[u8]> = sets.iter().map(Vec::as_slice).collect();
+        prop_assert_eq!(Capabilities::from_data(&data, &sets), Ok(caps));
+    }
+}
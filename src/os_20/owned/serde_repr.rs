@@ -0,0 +1,322 @@
+//! Human-readable serde representation of feature descriptors
+//!
+//! Values are serialized in a readable form whenever this is lossless, otherwise raw values are
+//! used, so (de)serialization always round-trips:
+//!
+//! * compatible IDs as ASCII strings without trailing NULs, e.g. `"WINUSB"`
+//! * registry property names as strings without the NULL terminator
+//! * registry property data depending on `data_type`: strings for `RegSz`/`RegExpandSz`/`RegLink`,
+//!   lists of strings for `RegMultiSz`, integers for `RegDword*`, byte lists otherwise
+//! * model ID as a GUID string, e.g. `"6B09AAC4-333F-4467-9E23-F88B9E9D95F7"`, encoded with
+//!   little-endian fields the same way as the MS OS 2.0 platform capability UUID
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use serde::{Deserialize, Serialize};
+
+use crate::os_20::PropertyDataType;
+
+use super::{utf16_null_bytes, utf16_null_str, utf16_null_strings, FeatureDescriptor};
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub(super) enum FeatureRepr {
+    CompatibleId {
+        id: IdRepr,
+        #[serde(default)]
+        sub_id: IdRepr,
+    },
+    RegistryProperty {
+        data_type: PropertyDataType,
+        name: NameRepr,
+        data: DataRepr,
+    },
+    ResumeTime {
+        recovery: u8,
+        signaling: u8,
+    },
+    ModelId {
+        id: ModelIdRepr,
+    },
+    CcgpDevice,
+    VendorRevision {
+        revision: u16,
+    },
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(super) enum IdRepr {
+    Text(String),
+    Bytes([u8; 8]),
+}
+
+impl Default for IdRepr {
+    fn default() -> Self {
+        Self::Text(String::new())
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(super) enum NameRepr {
+    Text(String),
+    Raw(Vec<u16>),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(super) enum DataRepr {
+    Integer(u32),
+    Text(String),
+    // Before MultiText so that empty list is deserialized as bytes
+    Bytes(Vec<u8>),
+    MultiText(Vec<String>),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(super) enum ModelIdRepr {
+    Guid(String),
+    Bytes([u8; 16]),
+}
+
+impl IdRepr {
+    fn new(id: &[u8; 8]) -> Self {
+        let len = id.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+        let text = &id[..len];
+        if text.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+            // Cannot fail as all bytes are ASCII
+            Self::Text(String::from_utf8(text.to_vec()).unwrap())
+        } else {
+            Self::Bytes(*id)
+        }
+    }
+
+    fn into_id(self) -> Result<[u8; 8], &'static str> {
+        match self {
+            Self::Text(text) => {
+                if !text.is_ascii() || text.len() > 8 {
+                    return Err("compatible ID must be an ASCII string of at most 8 characters");
+                }
+                let mut id = [0; 8];
+                id[..text.len()].copy_from_slice(text.as_bytes());
+                Ok(id)
+            },
+            Self::Bytes(id) => Ok(id),
+        }
+    }
+}
+
+impl NameRepr {
+    fn new(name: &[u16]) -> Self {
+        match utf16_null_str(name) {
+            Some(text) => Self::Text(text),
+            None => Self::Raw(name.to_vec()),
+        }
+    }
+
+    fn into_name(self) -> Vec<u16> {
+        match self {
+            Self::Text(text) => text.encode_utf16().chain([0]).collect(),
+            Self::Raw(name) => name,
+        }
+    }
+}
+
+impl DataRepr {
+    fn new(data_type: PropertyDataType, data: &[u8]) -> Self {
+        use PropertyDataType::*;
+        let strings = utf16_null_strings(data).unwrap_or_default();
+        match (data_type, strings.as_slice()) {
+            (RegSz | RegExpandSz | RegLink, [text]) => Self::Text(text.clone()),
+            // Non-empty strings followed by an empty one (the terminating NULL)
+            (RegMutliSz, [texts @ .., last]) if !texts.is_empty()
+                && last.is_empty()
+                && texts.iter().all(|s| !s.is_empty()) => Self::MultiText(texts.to_vec()),
+            (RegDwordLittleEndian, _) if data.len() == 4 => Self::Integer(u32::from_le_bytes(data.try_into().unwrap())),
+            (RegDwordBigEndian, _) if data.len() == 4 => Self::Integer(u32::from_be_bytes(data.try_into().unwrap())),
+            _ => Self::Bytes(data.to_vec()),
+        }
+    }
+
+    fn into_data(self, data_type: PropertyDataType) -> Result<Vec<u8>, &'static str> {
+        use PropertyDataType::*;
+        match (self, data_type) {
+            (Self::Bytes(data), _) => Ok(data),
+            (Self::Integer(value), RegDwordLittleEndian) => Ok(value.to_le_bytes().to_vec()),
+            (Self::Integer(value), RegDwordBigEndian) => Ok(value.to_be_bytes().to_vec()),
+            (Self::Integer(_), _) => Err("integer data is only allowed for RegDword* properties"),
+            (Self::Text(text), RegSz | RegExpandSz | RegLink) => {
+                if text.contains('\0') {
+                    return Err("string data must not contain NULL");
+                }
+                Ok(utf16_null_bytes([text.as_str()]))
+            },
+            (Self::Text(_), _) => Err("string data is only allowed for RegSz, RegExpandSz and RegLink properties"),
+            (Self::MultiText(strings), RegMutliSz) => {
+                if strings.iter().any(|s| s.is_empty() || s.contains('\0')) {
+                    return Err("RegMultiSz strings must be non-empty and must not contain NULL");
+                }
+                Ok(utf16_null_bytes(strings.iter().map(String::as_str).chain([""])))
+            },
+            (Self::MultiText(_), _) => Err("list of strings is only allowed for RegMultiSz properties"),
+        }
+    }
+}
+
+/// Order of bytes in GUID string for little-endian encoded fields
+const GUID_BYTE_ORDER: [usize; 16] = [3, 2, 1, 0, 5, 4, 7, 6, 8, 9, 10, 11, 12, 13, 14, 15];
+
+impl ModelIdRepr {
+    fn new(id: &[u8; 16]) -> Self {
+        let hex: Vec<String> = GUID_BYTE_ORDER.iter().map(|&i| format!("{:02X}", id[i])).collect();
+        Self::Guid(format!("{}-{}-{}-{}-{}",
+            hex[..4].concat(), hex[4..6].concat(), hex[6..8].concat(), hex[8..10].concat(), hex[10..].concat()))
+    }
+
+    fn into_id(self) -> Result<[u8; 16], &'static str> {
+        const INVALID: &str = "model ID must be a GUID string in format XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX";
+        match self {
+            Self::Guid(guid) => {
+                let guid = guid.strip_prefix('{')
+                    .and_then(|g| g.strip_suffix('}'))
+                    .unwrap_or(&guid);
+                let groups: Vec<&str> = guid.split('-').collect();
+                if groups.iter().map(|g| g.len()).ne([8, 4, 4, 4, 12])
+                    || !groups.iter().all(|g| g.bytes().all(|b| b.is_ascii_hexdigit()))
+                {
+                    return Err(INVALID);
+                }
+                let hex = groups.concat();
+                let mut id = [0; 16];
+                for (n, &i) in GUID_BYTE_ORDER.iter().enumerate() {
+                    id[i] = hex.get(2 * n..2 * n + 2)
+                        .and_then(|b| u8::from_str_radix(b, 16).ok())
+                        .ok_or(INVALID)?;
+                }
+                Ok(id)
+            },
+            Self::Bytes(id) => Ok(id),
+        }
+    }
+}
+
+impl From<FeatureDescriptor> for FeatureRepr {
+    fn from(feature: FeatureDescriptor) -> Self {
+        match feature {
+            FeatureDescriptor::CompatibleId { id, sub_id } => Self::CompatibleId {
+                id: IdRepr::new(&id),
+                sub_id: IdRepr::new(&sub_id),
+            },
+            FeatureDescriptor::RegistryProperty { data_type, name, data } => Self::RegistryProperty {
+                data_type,
+                name: NameRepr::new(&name),
+                data: DataRepr::new(data_type, &data),
+            },
+            FeatureDescriptor::ResumeTime { recovery, signaling } => Self::ResumeTime { recovery, signaling },
+            FeatureDescriptor::ModelId { id } => Self::ModelId { id: ModelIdRepr::new(&id) },
+            FeatureDescriptor::CcgpDevice => Self::CcgpDevice,
+            FeatureDescriptor::VendorRevision { revision } => Self::VendorRevision { revision },
+        }
+    }
+}
+
+impl TryFrom<FeatureRepr> for FeatureDescriptor {
+    type Error = &'static str;
+
+    fn try_from(feature: FeatureRepr) -> Result<Self, Self::Error> {
+        Ok(match feature {
+            FeatureRepr::CompatibleId { id, sub_id } => Self::CompatibleId {
+                id: id.into_id()?,
+                sub_id: sub_id.into_id()?,
+            },
+            FeatureRepr::RegistryProperty { data_type, name, data } => Self::RegistryProperty {
+                data_type,
+                name: name.into_name(),
+                data: data.into_data(data_type)?,
+            },
+            FeatureRepr::ResumeTime { recovery, signaling } => Self::ResumeTime { recovery, signaling },
+            FeatureRepr::ModelId { id } => Self::ModelId { id: id.into_id()? },
+            FeatureRepr::CcgpDevice => Self::CcgpDevice,
+            FeatureRepr::VendorRevision { revision } => Self::VendorRevision { revision },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os_20::owned::DescriptorSet;
+    use crate::os_20::tests::EXAMPLE_SET;
+    use alloc::vec;
+
+    fn round_trip(feature: FeatureDescriptor) -> serde_json::Value {
+        let json = serde_json::to_value(&feature).unwrap();
+        assert_eq!(serde_json::from_value::<FeatureDescriptor>(json.clone()).unwrap(), feature);
+        json
+    }
+
+    #[test]
+    fn readable_values() {
+        let json = round_trip(FeatureDescriptor::CompatibleId { id: *b"WINUSB\0\0", sub_id: [0; 8] });
+        assert_eq!(json, serde_json::json!({ "type": "CompatibleId", "id": "WINUSB", "sub_id": "" }));
+
+        let json = round_trip(FeatureDescriptor::RegistryProperty {
+            data_type: PropertyDataType::RegMutliSz,
+            name: "Name\0".encode_utf16().collect(),
+            data: utf16_null_bytes(["a", "b", ""]),
+        });
+        assert_eq!(json["name"], "Name");
+        assert_eq!(json["data"], serde_json::json!(["a", "b"]));
+        assert_eq!(json["data_type"], "RegMultiSz");
+        let misspelled: PropertyDataType = serde_json::from_value(serde_json::json!("RegMutliSz")).unwrap();
+        assert_eq!(misspelled, PropertyDataType::RegMutliSz);
+
+        let json = round_trip(FeatureDescriptor::RegistryProperty {
+            data_type: PropertyDataType::RegDwordBigEndian,
+            name: vec![0x41],
+            data: vec![0, 0, 1, 2],
+        });
+        assert_eq!(json["name"], serde_json::json!([0x41]));
+        assert_eq!(json["data"], 0x102);
+
+        let json = round_trip(FeatureDescriptor::ModelId {
+            id: [0xc4, 0xaa, 0x09, 0x6b, 0x3f, 0x33, 0x67, 0x44, 0x9e, 0x23, 0xf8, 0x8b, 0x9e, 0x9d, 0x95, 0xf7],
+        });
+        assert_eq!(json["id"], "6B09AAC4-333F-4467-9E23-F88B9E9D95F7");
+    }
+
+    #[test]
+    fn raw_values() {
+        // Not representable as readable values
+        round_trip(FeatureDescriptor::CompatibleId { id: [1, 2, 3, 4, 5, 6, 7, 8], sub_id: *b"A\0B\0\0\0\0\0" });
+        for data_type in [PropertyDataType::RegSz, PropertyDataType::RegMutliSz, PropertyDataType::RegBinary] {
+            for data in [vec![], vec![0, 0], vec![0x41], vec![0x41, 0, 0, 0, 0x41, 0], vec![0, 0xd8, 0, 0]] {
+                round_trip(FeatureDescriptor::RegistryProperty { data_type, name: vec![], data });
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_values() {
+        let parse = |json| serde_json::from_value::<FeatureDescriptor>(json);
+        assert!(parse(serde_json::json!({ "type": "CompatibleId", "id": "TOO_LONG_ID" })).is_err());
+        assert!(parse(serde_json::json!({ "type": "ModelId", "id": "6B09AAC4-333F-4467-9E23" })).is_err());
+        assert!(parse(serde_json::json!({
+            "type": "RegistryProperty", "data_type": "RegBinary", "name": "Name", "data": "text",
+        })).is_err());
+        assert!(parse(serde_json::json!({
+            "type": "RegistryProperty", "data_type": "RegMultiSz", "name": "Name", "data": ["a", ""],
+        })).is_err());
+    }
+
+    #[test]
+    fn descriptor_set_round_trip() {
+        let set = DescriptorSet::from(&EXAMPLE_SET);
+        let json = serde_json::to_string(&set).unwrap();
+        assert_eq!(serde_json::from_str::<DescriptorSet>(&json).unwrap(), set);
+    }
+}
@@ -0,0 +1,153 @@
+//! Generate `.reg` files with registry values expected from MS OS 2.0 descriptors
+//!
+//! Registry properties are written under the `Device Parameters` key of the device or function
+//! instance, which is where Windows stores them, so the file can be compared with the actual
+//! registry state (e.g. exported using `reg export`).
+
+use core::fmt::Write;
+use std::string::String;
+use std::vec::Vec;
+
+use crate::os_20::owned::{self, utf16_null_bytes};
+use crate::os_20::PropertyDataType;
+use crate::registry::{self, Error, Value};
+
+/// Device information used in registry key paths
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegOptions<'a> {
+    /// USB Vendor ID
+    pub vid: u16,
+    /// USB Product ID
+    pub pid: u16,
+    /// Only generate values of the function with this first interface, all values if `None`
+    pub interface: Option<u8>,
+    /// Device instance ID, usually the serial number string for devices that have one
+    ///
+    /// Only used for the key of the whole device, see [`Self::function_instances`].
+    pub instance: &'a str,
+    /// Instance IDs of functions of a composite device, by their first interface
+    ///
+    /// Each function is a separate child device with instance ID generated by Windows (e.g.
+    /// `6&1a2b3c4d&0&0001`, shown in Device Manager as the last part of "Device instance path"),
+    /// unrelated to the serial number.
+    pub function_instances: &'a [(u8, &'a str)],
+}
+
+/// Generate `.reg` file contents with registry properties of a descriptor set
+///
+/// Returns [`Error::MissingFunctionInstance`] if a function with registry properties has no
+/// entry in [`RegOptions::function_instances`] (unless skipped using [`RegOptions::interface`]).
+pub fn generate(set: &owned::DescriptorSet, options: &RegOptions) -> Result<String, Error> {
+    let mut out = String::new();
+    writeln!(out, "Windows Registry Editor Version 5.00").unwrap();
+
+    let targets = registry::targets(set)?;
+    let targets = targets.iter()
+        .filter(|target| !target.properties.is_empty())
+        .filter(|target| options.interface.is_none() || target.interface == options.interface);
+    for target in targets {
+        let instance = match target.interface {
+            None => options.instance,
+            Some(interface) => options.function_instances.iter()
+                .find(|(first_interface, _)| *first_interface == interface)
+                .map(|(_, instance)| *instance)
+                .ok_or(Error::MissingFunctionInstance(interface))?,
+        };
+        writeln!(out).unwrap();
+        writeln!(out, "[HKEY_LOCAL_MACHINE\\SYSTEM\\CurrentControlSet\\Enum\\{}\\{}\\Device Parameters]",
+            target.hardware_id(options.vid, options.pid), instance).unwrap();
+        for property in &target.properties {
+            writeln!(out, "{}={}", quote(&property.name), reg_value(&property.value)).unwrap();
+        }
+    }
+
+    Ok(out)
+}
+
+/// Quote string escaping backslashes and quotes
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn hex(value_type: Option<PropertyDataType>, data: &[u8]) -> String {
+    let bytes: Vec<_> = data.iter().map(|b| format!("{:02x}", b)).collect();
+    match value_type {
+        Some(value_type) => format!("hex({:x}):{}", value_type as u16, bytes.join(",")),
+        None => format!("hex:{}", bytes.join(",")),
+    }
+}
+
+fn reg_value(value: &Value) -> String {
+    match value {
+        // Quoted strings cannot contain line breaks
+        Value::Sz(s) if s.chars().any(char::is_control) => {
+            hex(Some(PropertyDataType::RegSz), &utf16_null_bytes([s.as_str()]))
+        },
+        Value::Sz(s) => quote(s),
+        Value::ExpandSz(s) => hex(Some(PropertyDataType::RegExpandSz), &utf16_null_bytes([s.as_str()])),
+        Value::MultiSz(strings) => {
+            let data = utf16_null_bytes(strings.iter().map(String::as_str).chain([""]));
+            hex(Some(PropertyDataType::RegMutliSz), &data)
+        },
+        Value::Dword(value) => format!("dword:{:08x}", value),
+        Value::Raw(PropertyDataType::RegBinary, data) => hex(None, data),
+        Value::Raw(data_type, data) => hex(Some(*data_type), data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os_20::tests::EXAMPLE_SET;
+
+    #[test]
+    fn example_reg() {
+        let options = RegOptions {
+            vid: 0x1209,
+            pid: 0x0001,
+            interface: None,
+            instance: "0123",
+            function_instances: &[(0, "6&1a2b3c4d&0&0000"), (1, "6&1a2b3c4d&0&0001")],
+        };
+        let reg = generate(&owned::DescriptorSet::from(&EXAMPLE_SET), &options).unwrap();
+        let guid = "{897d7b90-5aae-43e5-9c36-aa0f2fdbafc9}";
+        assert_eq!(reg, format!("Windows Registry Editor Version 5.00
+
+[HKEY_LOCAL_MACHINE\\SYSTEM\\CurrentControlSet\\Enum\\USB\\VID_1209&PID_0001&MI_01\\6&1a2b3c4d&0&0001\\Device Parameters]
+\"DeviceInterfaceGUIDs\"={}
+", hex(Some(PropertyDataType::RegMutliSz), &utf16_null_bytes([guid, ""]))));
+
+        let options = RegOptions { interface: Some(0), ..options };
+        let reg = generate(&owned::DescriptorSet::from(&EXAMPLE_SET), &options).unwrap();
+        assert_eq!(reg, "Windows Registry Editor Version 5.00\n");
+    }
+
+    #[test]
+    fn function_instances() {
+        let mut set = owned::DescriptorSet::from(&EXAMPLE_SET);
+        set.features.push(owned::FeatureDescriptor::RegistryProperty {
+            data_type: PropertyDataType::RegDwordLittleEndian,
+            name: "Value\0".encode_utf16().collect(),
+            data: vec![1, 0, 0, 0],
+        });
+        let options = RegOptions { vid: 0x1209, pid: 0x0001, interface: None, instance: "0123", function_instances: &[] };
+        assert_eq!(generate(&set, &options), Err(Error::MissingFunctionInstance(1)));
+
+        // Device level values use the device instance ID
+        let options = RegOptions { function_instances: &[(1, "6&1a2b3c4d&0&0001")], ..options };
+        let reg = generate(&set, &options).unwrap();
+        assert!(reg.contains("\\USB\\VID_1209&PID_0001&MI_01\\6&1a2b3c4d&0&0001\\Device Parameters]\n"), "{}", reg);
+        assert!(reg.contains("\\USB\\VID_1209&PID_0001\\0123\\Device Parameters]\n\"Value\"=dword:00000001\n"), "{}", reg);
+    }
+
+    #[test]
+    fn reg_values() {
+        assert_eq!(reg_value(&Value::Sz("C:\\a \"b\"".into())), r#""C:\\a \"b\"""#);
+        assert_eq!(reg_value(&Value::Sz("a\n".into())), "hex(1):61,00,0a,00,00,00");
+        assert_eq!(reg_value(&Value::ExpandSz("a".into())), "hex(2):61,00,00,00");
+        assert_eq!(reg_value(&Value::MultiSz(vec!["a".into(), "b".into()])), "hex(7):61,00,00,00,62,00,00,00,00,00");
+        assert_eq!(reg_value(&Value::Dword(0x1234)), "dword:00001234");
+        assert_eq!(reg_value(&Value::Raw(PropertyDataType::RegBinary, vec![1, 0xff])), "hex:01,ff");
+        assert_eq!(reg_value(&Value::Raw(PropertyDataType::RegDwordBigEndian, vec![0, 0, 0, 1])), "hex(5):00,00,00,01");
+    }
+}
@@ -0,0 +1,189 @@
+//! Registry values resulting from MS OS 2.0 descriptors
+//!
+//! Windows applies feature descriptors of a descriptor set either to the whole device
+//! (device-level and configuration-level features) or to a single function (function subset
+//! features). This module resolves the descriptor set into these targets (see
+//! [`crate::registry::targets`]), with registry properties decoded into typed values, as used by
+//! host-side file generators.
+
+use std::format;
+use std::string::String;
+use std::vec::Vec;
+
+use crate::os_20::owned::{self, utf16_null_str, utf16_null_strings};
+use crate::os_20::PropertyDataType;
+
+/// Errors when resolving registry values
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Registry property name is not a NULL-terminated UTF-16 string
+    InvalidPropertyName,
+    /// Descriptor set has no WinUSB compatible ID
+    NoWinUsb,
+    /// Value of a string registry property cannot be written to an INF file, contains property name
+    UnsupportedInfValue(String),
+    /// No instance ID given for the function with this first interface
+    MissingFunctionInstance(u8),
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidPropertyName => f.write_str("registry property name is not a NULL-terminated UTF-16 string"),
+            Self::NoWinUsb => f.write_str("descriptor set has no WINUSB compatible ID"),
+            Self::UnsupportedInfValue(name) => write!(f, "value of registry property {:?} cannot be written to INF", name),
+            Self::MissingFunctionInstance(interface) =>
+                write!(f, "no instance ID given for function with first interface {}", interface),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Typed registry value
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    /// REG_SZ
+    Sz(String),
+    /// REG_EXPAND_SZ
+    ExpandSz(String),
+    /// REG_MULTI_SZ
+    MultiSz(Vec<String>),
+    /// REG_DWORD (REG_DWORD_LITTLE_ENDIAN)
+    Dword(u32),
+    /// Raw data of given type, used for binary types or data that does not match its type
+    Raw(PropertyDataType, Vec<u8>),
+}
+
+impl Value {
+    /// Decode registry property data
+    pub fn new(data_type: PropertyDataType, data: &[u8]) -> Self {
+        use PropertyDataType::*;
+        let strings = utf16_null_strings(data).unwrap_or_default();
+        match (data_type, strings.as_slice()) {
+            (RegSz, [text]) => Self::Sz(text.clone()),
+            (RegExpandSz, [text]) => Self::ExpandSz(text.clone()),
+            // REG_MULTI_SZ cannot contain empty strings, the first one would end the list
+            (RegMutliSz, [texts @ .., last]) if last.is_empty() && texts.iter().all(|t| !t.is_empty()) => {
+                Self::MultiSz(texts.to_vec())
+            },
+            (RegDwordLittleEndian, _) if data.len() == 4 => {
+                Self::Dword(u32::from_le_bytes([data[0], data[1], data[2], data[3]]))
+            },
+            _ => Self::Raw(data_type, data.to_vec()),
+        }
+    }
+}
+
+/// Registry property with decoded name and value
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Property {
+    /// Value name
+    pub name: String,
+    /// Value data
+    pub value: Value,
+}
+
+/// Device or function to which features apply
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Target<'a> {
+    /// Interface number of function, `None` for the whole device
+    pub interface: Option<u8>,
+    /// Compatible ID and sub-compatible ID
+    pub compatible_id: Option<(&'a [u8; 8], &'a [u8; 8])>,
+    /// Registry properties
+    pub properties: Vec<Property>,
+    /// Features that do not result in registry values
+    pub other_features: Vec<&'a owned::FeatureDescriptor>,
+}
+
+impl Target<'_> {
+    /// Check if compatible ID is WINUSB
+    pub fn is_winusb(&self) -> bool {
+        matches!(self.compatible_id, Some((id, _)) if id == b"WINUSB\0\0")
+    }
+
+    /// Hardware ID of the device or function, e.g. `USB\VID_1234&PID_5678&MI_02`
+    pub fn hardware_id(&self, vid: u16, pid: u16) -> String {
+        match self.interface {
+            Some(interface) => format!("USB\\VID_{:04X}&PID_{:04X}&MI_{:02X}", vid, pid, interface),
+            None => format!("USB\\VID_{:04X}&PID_{:04X}", vid, pid),
+        }
+    }
+}
+
+fn target<'a>(
+    interface: Option<u8>,
+    features: impl IntoIterator<Item = &'a owned::FeatureDescriptor>,
+) -> Result<Target<'a>, Error> {
+    let mut target = Target { interface, compatible_id: None, properties: Vec::new(), other_features: Vec::new() };
+    for feature in features {
+        match feature {
+            owned::FeatureDescriptor::CompatibleId { id, sub_id } => target.compatible_id = Some((id, sub_id)),
+            owned::FeatureDescriptor::RegistryProperty { data_type, name, data } => {
+                target.properties.push(Property {
+                    name: utf16_null_str(name).ok_or(Error::InvalidPropertyName)?,
+                    value: Value::new(*data_type, data),
+                });
+            },
+            _ => target.other_features.push(feature),
+        }
+    }
+    Ok(target)
+}
+
+/// Resolve descriptor set into device target followed by function targets
+///
+/// Configuration-level features are applied to the device, as Windows only uses a single
+/// configuration.
+pub fn targets(set: &owned::DescriptorSet) -> Result<Vec<Target<'_>>, Error> {
+    let device_features = set.features.iter()
+        .chain(set.configurations.iter().flat_map(|config| &config.features));
+    let mut targets = vec![target(None, device_features)?];
+    for function in set.configurations.iter().flat_map(|config| &config.functions) {
+        targets.push(target(Some(function.first_interface), &function.features)?);
+    }
+    Ok(targets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os_20::tests::EXAMPLE_SET;
+
+    #[test]
+    fn values() {
+        let utf16 = |s: &str| -> Vec<u8> { s.encode_utf16().flat_map(u16::to_le_bytes).collect() };
+        assert_eq!(Value::new(PropertyDataType::RegSz, &utf16("abc\0")), Value::Sz("abc".into()));
+        assert_eq!(Value::new(PropertyDataType::RegExpandSz, &utf16("%a%\0")), Value::ExpandSz("%a%".into()));
+        assert_eq!(Value::new(PropertyDataType::RegMutliSz, &utf16("a\0b\0\0")),
+            Value::MultiSz(vec!["a".into(), "b".into()]));
+        assert_eq!(Value::new(PropertyDataType::RegDwordLittleEndian, &[1, 2, 0, 0]), Value::Dword(0x201));
+        assert_eq!(Value::new(PropertyDataType::RegMutliSz, &utf16("a\0\0b\0\0")),
+            Value::Raw(PropertyDataType::RegMutliSz, utf16("a\0\0b\0\0")));
+        assert_eq!(Value::new(PropertyDataType::RegSz, &[0x41, 0]),
+            Value::Raw(PropertyDataType::RegSz, vec![0x41, 0]));
+        assert_eq!(Value::new(PropertyDataType::RegDwordBigEndian, &[0, 0, 0, 1]),
+            Value::Raw(PropertyDataType::RegDwordBigEndian, vec![0, 0, 0, 1]));
+    }
+
+    #[test]
+    fn example_targets() {
+        let mut set = owned::DescriptorSet::from(&EXAMPLE_SET);
+        let targets = targets(&set).unwrap();
+        assert_eq!(targets.len(), 2);
+        assert_eq!(targets[0].interface, None);
+        assert!(!targets[0].is_winusb());
+        assert!(targets[0].properties.is_empty());
+        assert_eq!(targets[1].interface, Some(1));
+        assert!(targets[1].is_winusb());
+        assert_eq!(targets[1].properties, [Property {
+            name: "DeviceInterfaceGUIDs".into(),
+            value: Value::MultiSz(vec!["{897d7b90-5aae-43e5-9c36-aa0f2fdbafc9}".into()]),
+        }]);
+        assert_eq!(targets[1].hardware_id(0x1234, 0xabcd), "USB\\VID_1234&PID_ABCD&MI_01");
+
+        set.features.push(owned::FeatureDescriptor::CcgpDevice);
+        assert_eq!(super::targets(&set).unwrap()[0].other_features, [&owned::FeatureDescriptor::CcgpDevice]);
+    }
+}
@@ -0,0 +1,722 @@
+//! Analyze MS OS descriptor traffic in Linux usbmon captures
+//!
+//! Reads pcap or pcapng captures of usbmon interfaces (e.g. from Wireshark or
+//! `tcpdump -i usbmon1 -w capture.pcap`), finds control transfers related to MS OS descriptors
+//! (BOS descriptor, MS OS 1.0 string descriptor and vendor requests, MS OS 2.0 descriptor set
+//! requests) and decodes them using [`crate::os_20::owned`]. Each transfer is checked for
+//! problems that commonly break enumeration on Windows, most importantly lengths returned by
+//! the device that do not match the lengths advertised in descriptors.
+//!
+//! MS OS 1.0 descriptors are only checked for lengths and vendor codes, as this crate does
+//! not implement them. The usbmon headers are assumed to be little-endian.
+
+use std::collections::HashMap;
+use std::vec::Vec;
+
+use crate::os_20::owned::{self, CapabilitySetInfo, DecodeError};
+use crate::os_20::{Capabilities, DescriptorIndex};
+
+/// Errors when reading a capture
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Data is neither a pcap nor a pcapng file
+    UnknownFormat,
+    /// Capture ends in the middle of a record/block starting at given offset
+    UnexpectedEnd {
+        /// Offset of the record/block
+        offset: usize,
+    },
+    /// Invalid pcapng block length at given offset
+    InvalidBlock {
+        /// Offset of the block
+        offset: usize,
+    },
+    /// Capture does not contain any usbmon interface
+    NoUsbmon,
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::UnknownFormat => f.write_str("not a pcap or pcapng capture"),
+            Self::UnexpectedEnd { offset } => write!(f, "capture ends in the middle of record at offset {}", offset),
+            Self::InvalidBlock { offset } => write!(f, "invalid pcapng block length at offset {}", offset),
+            Self::NoUsbmon => f.write_str("capture does not contain usbmon packets (LINKTYPE_USB_LINUX)"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Setup packet of a control transfer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Setup {
+    /// bmRequestType
+    pub request_type: u8,
+    /// bRequest
+    pub request: u8,
+    /// wValue
+    pub value: u16,
+    /// wIndex
+    pub index: u16,
+    /// wLength
+    pub length: u16,
+}
+
+impl Setup {
+    fn from_bytes(b: [u8; 8]) -> Self {
+        Self {
+            request_type: b[0],
+            request: b[1],
+            value: u16::from_le_bytes([b[2], b[3]]),
+            index: u16::from_le_bytes([b[4], b[5]]),
+            length: u16::from_le_bytes([b[6], b[7]]),
+        }
+    }
+
+    /// Classify request, `os10_vendor_code` is bMS_VendorCode from the device's OS string descriptor
+    fn kind(&self, os10_vendor_code: Option<u8>) -> Option<TransferKind> {
+        const GET_DESCRIPTOR: u8 = 6;
+        const BOS: u8 = 0x0f;
+        const OS_STRING: u16 = 0x03ee;
+        let vendor = self.request_type & 0x60 == 0x40;
+        let device_to_host = self.request_type & 0x80 != 0;
+        match (self.request_type, self.request, self.index) {
+            (0x80, GET_DESCRIPTOR, _) if self.value >> 8 == BOS as u16 => Some(TransferKind::Bos),
+            (0x80, GET_DESCRIPTOR, _) if self.value == OS_STRING => Some(TransferKind::OsString),
+            (_, request, 4 | 5) if vendor && device_to_host && os10_vendor_code == Some(request) => {
+                Some(TransferKind::Os10Descriptor)
+            },
+            (_, _, index) if vendor && device_to_host && index == DescriptorIndex::Descriptor as u16 => {
+                Some(TransferKind::Os20DescriptorSet)
+            },
+            (_, _, index) if vendor && !device_to_host && index == DescriptorIndex::SetAltEnumeration as u16 => {
+                Some(TransferKind::Os20SetAltEnumeration)
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Kind of MS OS related control transfer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransferKind {
+    /// GET_DESCRIPTOR request for the BOS descriptor
+    Bos,
+    /// GET_DESCRIPTOR request for the MS OS 1.0 string descriptor (index 0xEE)
+    OsString,
+    /// MS OS 1.0 extended compat ID (wIndex 4) or extended properties (wIndex 5) request
+    ///
+    /// Only recognized after the OS string descriptor, bRequest must match its bMS_VendorCode.
+    Os10Descriptor,
+    /// MS OS 2.0 descriptor set request
+    Os20DescriptorSet,
+    /// MS OS 2.0 set alternate enumeration command
+    Os20SetAltEnumeration,
+}
+
+impl core::fmt::Display for TransferKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::Bos => "BOS descriptor",
+            Self::OsString => "MS OS 1.0 string descriptor",
+            Self::Os10Descriptor => "MS OS 1.0 descriptor",
+            Self::Os20DescriptorSet => "MS OS 2.0 descriptor set",
+            Self::Os20SetAltEnumeration => "MS OS 2.0 set alternate enumeration",
+        })
+    }
+}
+
+/// Decoded transfer data
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Decoded {
+    /// Descriptor sets advertised in MS OS 2.0 platform capability of the BOS descriptor
+    Capability(Vec<CapabilitySetInfo>),
+    /// MS OS 1.0 string descriptor with bMS_VendorCode
+    OsString {
+        /// bMS_VendorCode
+        vendor_code: u8,
+    },
+    /// MS OS 2.0 descriptor set
+    DescriptorSet(owned::DescriptorSet),
+}
+
+/// Problem found in a transfer
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// Transfer failed with given URB status (e.g. -32 when the device stalled)
+    Failed(i32),
+    /// Captured data is shorter than returned data (capture snapshot length or usbmon limit)
+    Truncated {
+        /// Number of captured bytes
+        captured: usize,
+        /// Number of bytes returned by the device
+        returned: usize,
+    },
+    /// Returned length does not match the length advertised in a descriptor
+    LengthMismatch {
+        /// Field that advertises the length, e.g. `wTotalLength`
+        field: &'static str,
+        /// Expected length, the advertised length limited by the request wLength
+        expected: usize,
+        /// Number of bytes returned by the device
+        returned: usize,
+    },
+    /// Request uses vendor code not advertised by the device
+    UnknownVendorCode(u8),
+    /// BOS descriptor or MS OS 1.0 string descriptor is malformed
+    InvalidDescriptor,
+    /// Descriptor data could not be decoded
+    Decode(DecodeError),
+}
+
+impl core::fmt::Display for Problem {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Failed(status) => write!(f, "transfer failed with status {}", status),
+            Self::Truncated { captured, returned } => {
+                write!(f, "only {} of {} returned bytes captured", captured, returned)
+            },
+            Self::LengthMismatch { field, expected, returned } => {
+                write!(f, "{} implies {} bytes but {} were returned", field, expected, returned)
+            },
+            Self::UnknownVendorCode(code) => write!(f, "vendor code {} not advertised by the device", code),
+            Self::InvalidDescriptor => f.write_str("malformed descriptor"),
+            Self::Decode(e) => write!(f, "decoding failed: {}", e),
+        }
+    }
+}
+
+/// MS OS related control transfer found in a capture
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Transfer {
+    /// USB bus number
+    pub bus: u16,
+    /// Device address
+    pub device: u8,
+    /// Transfer kind
+    pub kind: TransferKind,
+    /// Setup packet
+    pub setup: Setup,
+    /// Number of bytes returned by the device
+    pub returned: usize,
+    /// Captured data
+    pub data: Vec<u8>,
+    /// Decoded data, if the transfer was complete and decoding succeeded
+    pub decoded: Option<Decoded>,
+    /// Problems found
+    pub problems: Vec<Problem>,
+}
+
+/// Little-endian or big-endian reader of file headers
+#[derive(Clone, Copy)]
+struct Endian {
+    big: bool,
+}
+
+impl Endian {
+    fn u16(self, data: &[u8], offset: usize) -> Option<u16> {
+        let bytes = data.get(offset..offset.checked_add(2)?)?.try_into().ok()?;
+        Some(if self.big { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+
+    fn u32(self, data: &[u8], offset: usize) -> Option<u32> {
+        let bytes = data.get(offset..offset.checked_add(4)?)?.try_into().ok()?;
+        Some(if self.big { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+}
+
+/// Get length of usbmon header for given link type
+fn usbmon_header_len(link_type: u32) -> Option<usize> {
+    const LINKTYPE_USB_LINUX: u32 = 189;
+    const LINKTYPE_USB_LINUX_MMAPPED: u32 = 220;
+    match link_type {
+        LINKTYPE_USB_LINUX => Some(48),
+        LINKTYPE_USB_LINUX_MMAPPED => Some(64),
+        _ => None,
+    }
+}
+
+/// Get usbmon packets with their header length from pcap file
+fn pcap(data: &[u8]) -> Result<Vec<(usize, &[u8])>, Error> {
+    let big = match (Endian { big: false }).u32(data, 0) {
+        Some(0xa1b2c3d4 | 0xa1b23c4d) => false,
+        Some(0xd4c3b2a1 | 0x4d3cb2a1) => true,
+        _ => return Err(Error::UnknownFormat),
+    };
+    let endian = Endian { big };
+    let link_type = endian.u32(data, 20).ok_or(Error::UnexpectedEnd { offset: 0 })?;
+    // Upper bits may contain FCS information
+    let header_len = usbmon_header_len(link_type & 0xffff).ok_or(Error::NoUsbmon)?;
+
+    let mut packets = Vec::new();
+    let mut offset = 24;
+    while offset < data.len() {
+        let packet = endian.u32(data, offset + 8)
+            .and_then(|len| data.get(offset + 16..(offset + 16).checked_add(len as usize)?))
+            .ok_or(Error::UnexpectedEnd { offset })?;
+        packets.push((header_len, packet));
+        offset += 16 + packet.len();
+    }
+    Ok(packets)
+}
+
+/// Get usbmon packets with their header length from pcapng file
+fn pcapng(data: &[u8]) -> Result<Vec<(usize, &[u8])>, Error> {
+    const SECTION_HEADER: u32 = 0x0a0d0d0a;
+    const INTERFACE_DESCRIPTION: u32 = 1;
+    const PACKET: u32 = 2;
+    const SIMPLE_PACKET: u32 = 3;
+    const ENHANCED_PACKET: u32 = 6;
+
+    let mut endian = Endian { big: false };
+    if endian.u32(data, 0) != Some(SECTION_HEADER) {
+        return Err(Error::UnknownFormat);
+    }
+    let mut interfaces: Vec<Option<usize>> = Vec::new();
+    let mut any_usbmon = false;
+    let mut packets = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let block_type = endian.u32(data, offset).ok_or(Error::UnexpectedEnd { offset })?;
+        if block_type == SECTION_HEADER {
+            endian.big = match (Endian { big: false }).u32(data, offset + 8) {
+                Some(0x1a2b3c4d) => false,
+                Some(0x4d3c2b1a) => true,
+                _ => return Err(Error::UnknownFormat),
+            };
+            interfaces.clear();
+        }
+
+        let total_len = endian.u32(data, offset + 4).ok_or(Error::UnexpectedEnd { offset })? as usize;
+        if total_len < 12 || total_len % 4 != 0 {
+            return Err(Error::InvalidBlock { offset });
+        }
+        let body = data.get(offset + 8..)
+            .and_then(|rest| rest.get(..total_len - 12))
+            .ok_or(Error::UnexpectedEnd { offset })?;
+        let invalid = Error::InvalidBlock { offset };
+
+        let (interface, packet) = match block_type {
+            INTERFACE_DESCRIPTION => {
+                let header_len = usbmon_header_len(endian.u16(body, 0).ok_or(invalid)? as u32);
+                any_usbmon |= header_len.is_some();
+                interfaces.push(header_len);
+                (None, None)
+            },
+            ENHANCED_PACKET | PACKET => {
+                let interface = match block_type {
+                    PACKET => endian.u16(body, 0).map(|i| i as u32),
+                    _ => endian.u32(body, 0),
+                };
+                let packet = endian.u32(body, 12)
+                    .and_then(|len| body.get(20..20usize.checked_add(len as usize)?));
+                (Some(interface.ok_or(invalid.clone())?), Some(packet.ok_or(invalid)?))
+            },
+            SIMPLE_PACKET => {
+                let len = endian.u32(body, 0).ok_or(invalid.clone())? as usize;
+                let packet = body.get(4..).ok_or(invalid)?;
+                (Some(0), Some(&packet[..len.min(packet.len())]))
+            },
+            _ => (None, None),
+        };
+        if let (Some(interface), Some(packet)) = (interface, packet) {
+            if let Some(Some(header_len)) = interfaces.get(interface as usize) {
+                packets.push((*header_len, packet));
+            }
+        }
+        offset += total_len;
+    }
+
+    match any_usbmon {
+        true => Ok(packets),
+        false => Err(Error::NoUsbmon),
+    }
+}
+
+/// Parsed usbmon packet
+struct Urb<'a> {
+    id: u64,
+    event: u8,
+    transfer_type: u8,
+    device: u8,
+    bus: u16,
+    setup: Option<[u8; 8]>,
+    status: i32,
+    length: u32,
+    data: &'a [u8],
+}
+
+impl<'a> Urb<'a> {
+    fn parse(header_len: usize, packet: &'a [u8]) -> Option<Self> {
+        let header = packet.get(..header_len)?;
+        let le = Endian { big: false };
+        Some(Self {
+            id: u64::from_le_bytes(header[0..8].try_into().ok()?),
+            event: header[8],
+            transfer_type: header[9],
+            device: header[11],
+            bus: le.u16(header, 12)?,
+            setup: (header[14] == 0).then(|| header[40..48].try_into().ok()).flatten(),
+            status: le.u32(header, 28)? as i32,
+            length: le.u32(header, 32)?,
+            data: &packet[header_len..],
+        })
+    }
+}
+
+/// MS OS information advertised by a device so far
+#[derive(Default)]
+struct DeviceState {
+    sets: Option<Vec<CapabilitySetInfo>>,
+    os10_vendor_code: Option<u8>,
+}
+
+/// Decode MS OS 2.0 platform capability from BOS descriptor
+fn bos_capability(data: &[u8]) -> Result<Option<Vec<CapabilitySetInfo>>, Problem> {
+    const DEVICE_CAPABILITY: u8 = 0x10;
+    const PLATFORM: u8 = 0x05;
+    let mut offset = data.first().map(|&len| len as usize).ok_or(Problem::InvalidDescriptor)?;
+    let mut sets = None;
+    while offset < data.len() {
+        let capability = data.get(offset).map(|&len| len as usize)
+            .filter(|&len| len >= 3)
+            .and_then(|len| data.get(offset..offset + len))
+            .ok_or(Problem::InvalidDescriptor)?;
+        if capability[1] == DEVICE_CAPABILITY && capability[2] == PLATFORM
+            && capability.get(4..20) == Some(&Capabilities::CAPABILITY_ID[..])
+        {
+            sets = Some(owned::Capabilities::parse_data(&capability[3..]).map_err(Problem::Decode)?);
+        }
+        offset += capability.len();
+    }
+    Ok(sets)
+}
+
+/// Decode MS OS 1.0 string descriptor, returns bMS_VendorCode
+fn os_string(data: &[u8]) -> Option<u8> {
+    let signature = "MSFT100".encode_utf16().flat_map(u16::to_le_bytes);
+    let valid = data.len() == 18 && data[0] == 18 && data[1] == 3 && data[2..16].iter().copied().eq(signature);
+    valid.then_some(data[16])
+}
+
+/// Check length advertised in descriptor data at given offset
+fn check_length(transfer: &mut Transfer, field: &'static str, advertised: Option<usize>) {
+    if let Some(advertised) = advertised {
+        let expected = advertised.min(transfer.setup.length as usize);
+        if expected != transfer.returned {
+            transfer.problems.push(Problem::LengthMismatch { field, expected, returned: transfer.returned });
+        }
+    }
+}
+
+/// Decode transfer data and find problems
+fn analyze_transfer(transfer: &mut Transfer, state: &mut DeviceState) {
+    let le = Endian { big: false };
+    let data = transfer.data.clone();
+    let complete = transfer.problems.is_empty();
+    let returned = transfer.returned;
+    // Decode only if the device returned the whole descriptor
+    let whole = |advertised: Option<usize>| complete && advertised == Some(returned);
+
+    match transfer.kind {
+        TransferKind::Bos => {
+            let total_len = le.u16(&data, 2).map(usize::from);
+            check_length(transfer, "BOS wTotalLength", total_len);
+            if whole(total_len) {
+                match bos_capability(&data) {
+                    Ok(Some(sets)) => {
+                        state.sets = Some(sets.clone());
+                        transfer.decoded = Some(Decoded::Capability(sets));
+                    },
+                    Ok(None) => state.sets = Some(Vec::new()),
+                    Err(problem) => transfer.problems.push(problem),
+                }
+            }
+        },
+        TransferKind::OsString => {
+            check_length(transfer, "bLength", data.first().map(|&len| len as usize));
+            if whole(Some(18)) {
+                match os_string(&data) {
+                    Some(vendor_code) => {
+                        state.os10_vendor_code = Some(vendor_code);
+                        transfer.decoded = Some(Decoded::OsString { vendor_code });
+                    },
+                    None => transfer.problems.push(Problem::InvalidDescriptor),
+                }
+            }
+        },
+        TransferKind::Os10Descriptor => {
+            check_length(transfer, "dwLength", le.u32(&data, 0).map(|len| len as usize));
+        },
+        TransferKind::Os20DescriptorSet => {
+            let info = state.sets.as_ref().map(|sets| {
+                sets.iter().find(|info| info.vendor_code == transfer.setup.request)
+            });
+            match info {
+                Some(Some(info)) => {
+                    check_length(transfer, "wMSOSDescriptorSetTotalLength", Some(info.total_len as usize))
+                },
+                Some(None) => transfer.problems.push(Problem::UnknownVendorCode(transfer.setup.request)),
+                None => {},
+            }
+            let total_len = le.u16(&data, 8).map(usize::from);
+            check_length(transfer, "descriptor set wTotalLength", total_len);
+            if whole(total_len) {
+                match owned::DescriptorSet::from_bytes(&data) {
+                    Ok(set) => transfer.decoded = Some(Decoded::DescriptorSet(set)),
+                    Err(e) => transfer.problems.push(Problem::Decode(e)),
+                }
+            }
+        },
+        TransferKind::Os20SetAltEnumeration => {
+            if state.sets.as_ref().is_some_and(|sets| sets.iter().all(|info| info.vendor_code != transfer.setup.request)) {
+                transfer.problems.push(Problem::UnknownVendorCode(transfer.setup.request));
+            }
+        },
+    }
+}
+
+/// Find and decode MS OS related control transfers in a pcap or pcapng capture
+///
+/// Transfers are returned in the order of their completion. Submissions without completion
+/// (e.g. cancelled URBs) are ignored.
+pub fn analyze(capture: &[u8]) -> Result<Vec<Transfer>, Error> {
+    const CONTROL: u8 = 2;
+    let packets = match pcap(capture) {
+        Err(Error::UnknownFormat) => pcapng(capture)?,
+        result => result?,
+    };
+
+    let mut submitted: HashMap<u64, Setup> = HashMap::new();
+    let mut devices: HashMap<(u16, u8), DeviceState> = HashMap::new();
+    let mut transfers = Vec::new();
+    for urb in packets.into_iter().filter_map(|(header_len, packet)| Urb::parse(header_len, packet)) {
+        if urb.transfer_type != CONTROL {
+            continue;
+        }
+        match (urb.event, urb.setup) {
+            (b'S', Some(setup)) => {
+                submitted.insert(urb.id, Setup::from_bytes(setup));
+            },
+            (b'C' | b'E', _) => {
+                let Some(setup) = submitted.remove(&urb.id) else { continue };
+                let state = devices.entry((urb.bus, urb.device)).or_default();
+                let Some(kind) = setup.kind(state.os10_vendor_code) else { continue };
+                let mut transfer = Transfer {
+                    bus: urb.bus,
+                    device: urb.device,
+                    kind,
+                    setup,
+                    returned: urb.length as usize,
+                    data: urb.data.to_vec(),
+                    decoded: None,
+                    problems: Vec::new(),
+                };
+                if urb.event == b'E' || urb.status != 0 {
+                    transfer.problems.push(Problem::Failed(urb.status));
+                } else {
+                    if setup.request_type & 0x80 != 0 && urb.data.len() < transfer.returned {
+                        transfer.problems.push(Problem::Truncated {
+                            captured: urb.data.len(),
+                            returned: transfer.returned,
+                        });
+                    }
+                    analyze_transfer(&mut transfer, state);
+                }
+                transfers.push(transfer);
+            },
+            _ => {},
+        }
+    }
+    Ok(transfers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os_20::tests::EXAMPLE_SET;
+    use crate::WindowsVersion;
+
+    const BOS_SETUP: [u8; 8] = [0x80, 6, 0, 0x0f, 0, 0, 0xff, 0];
+
+    fn set_setup(len: usize) -> [u8; 8] {
+        let len = (len as u16).to_le_bytes();
+        [0xc0, 1, 0, 0, 7, 0, len[0], len[1]]
+    }
+
+    fn bos() -> Vec<u8> {
+        let capabilities = owned::Capabilities {
+            infos: vec![owned::CapabilityInfo { descriptors: (&EXAMPLE_SET).into(), alt_enum_cmd: 0 }],
+        };
+        let data = capabilities.data_to_vec().unwrap();
+        let total_len = (5 + 3 + data.len()) as u16;
+        let mut bos = vec![5, 0x0f, total_len as u8, (total_len >> 8) as u8, 1];
+        bos.extend([3 + data.len() as u8, 0x10, 5]);
+        bos.extend(data);
+        bos
+    }
+
+    /// usbmon packet with 48-byte header
+    fn urb(id: u64, event: u8, setup: Option<[u8; 8]>, status: i32, length: usize, data: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0; 48];
+        packet[0..8].copy_from_slice(&id.to_le_bytes());
+        packet[8] = event;
+        packet[9] = 2;
+        packet[10] = 0x80;
+        packet[11] = 5;
+        packet[12..14].copy_from_slice(&1u16.to_le_bytes());
+        packet[14] = if setup.is_some() { 0 } else { b'-' };
+        packet[28..32].copy_from_slice(&status.to_le_bytes());
+        packet[32..36].copy_from_slice(&(length as u32).to_le_bytes());
+        packet[36..40].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        packet[40..48].copy_from_slice(&setup.unwrap_or_default());
+        packet.extend(data);
+        packet
+    }
+
+    fn transfer(id: u64, setup: [u8; 8], status: i32, returned: usize, data: &[u8]) -> [Vec<u8>; 2] {
+        let requested = Setup::from_bytes(setup).length as usize;
+        [urb(id, b'S', Some(setup), 0, requested, &[]), urb(id, b'C', None, status, returned, data)]
+    }
+
+    fn pcap_file(packets: &[Vec<u8>]) -> Vec<u8> {
+        // Big-endian file, usbmon headers are still little-endian
+        let mut file = vec![];
+        for word in [0xa1b2c3d4, 0x0002_0004, 0, 0, 0xffff, 189u32] {
+            file.extend(word.to_be_bytes());
+        }
+        for packet in packets {
+            for word in [0, 0, packet.len() as u32, packet.len() as u32] {
+                file.extend(word.to_be_bytes());
+            }
+            file.extend(packet);
+        }
+        file
+    }
+
+    fn pcapng_file(packets: &[Vec<u8>]) -> Vec<u8> {
+        fn block(file: &mut Vec<u8>, block_type: u32, body: &[u8]) {
+            let padded = body.len().div_ceil(4) * 4;
+            let total_len = (12 + padded) as u32;
+            file.extend(block_type.to_le_bytes());
+            file.extend(total_len.to_le_bytes());
+            file.extend(body);
+            file.resize(file.len() + padded - body.len(), 0);
+            file.extend(total_len.to_le_bytes());
+        }
+        let mut file = vec![];
+        block(&mut file, 0x0a0d0d0a, &[0x4d, 0x3c, 0x2b, 0x1a, 1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        block(&mut file, 1, &[1, 0, 0, 0, 0, 0, 0, 0]); // Ethernet
+        block(&mut file, 1, &[189, 0, 0, 0, 0, 0, 0, 0]);
+        for packet in packets {
+            let mut body = vec![];
+            for word in [1, 0, 0, packet.len() as u32, packet.len() as u32] {
+                body.extend(word.to_le_bytes());
+            }
+            body.extend(packet);
+            block(&mut file, 6, &body);
+        }
+        file
+    }
+
+    #[test]
+    fn example_capture() {
+        let bos = bos();
+        let set = owned::DescriptorSet::from(&EXAMPLE_SET);
+        let set_bytes = set.to_bytes().unwrap();
+        let packets: Vec<_> = [
+            transfer(1, [0x80, 6, 0, 0x0f, 0, 0, 5, 0], 0, 5, &bos[..5]),
+            transfer(2, BOS_SETUP, 0, bos.len(), &bos),
+            transfer(3, [0x80, 6, 0, 1, 0, 0, 0x12, 0], 0, 18, &[0; 18]), // device descriptor
+            transfer(4, set_setup(set_bytes.len()), 0, set_bytes.len(), &set_bytes),
+        ].into_iter().flatten().collect();
+
+        let transfers = analyze(&pcap_file(&packets)).unwrap();
+        assert_eq!(transfers.len(), 3);
+        assert!(transfers.iter().all(|t| t.problems.is_empty() && t.bus == 1 && t.device == 5));
+        assert_eq!(transfers[0].kind, TransferKind::Bos);
+        assert_eq!(transfers[0].decoded, None);
+        assert_eq!(transfers[1].decoded, Some(Decoded::Capability(vec![CapabilitySetInfo {
+            version: EXAMPLE_SET.version,
+            total_len: set_bytes.len() as u16,
+            vendor_code: 1,
+            alt_enum_cmd: 0,
+        }])));
+        assert_eq!(transfers[2].kind, TransferKind::Os20DescriptorSet);
+        assert_eq!(transfers[2].decoded, Some(Decoded::DescriptorSet(set)));
+
+        assert_eq!(analyze(&pcapng_file(&packets)).unwrap(), transfers);
+    }
+
+    #[test]
+    fn unknown_capability_version() {
+        let mut bos = bos();
+        bos[25..29].copy_from_slice(&0x0A00_000Fu32.to_le_bytes());
+        let mut set = owned::DescriptorSet::from(&EXAMPLE_SET);
+        set.version = WindowsVersion::Other(0x0A00_000F);
+        let set_bytes = set.to_bytes().unwrap();
+        let packets: Vec<_> = [
+            transfer(1, BOS_SETUP, 0, bos.len(), &bos),
+            transfer(2, set_setup(set_bytes.len()), 0, set_bytes.len(), &set_bytes),
+        ].into_iter().flatten().collect();
+
+        let transfers = analyze(&pcap_file(&packets)).unwrap();
+        assert!(transfers.iter().all(|t| t.problems.is_empty()));
+        let Some(Decoded::Capability(infos)) = &transfers[0].decoded else { panic!() };
+        assert_eq!((infos[0].version, infos[0].vendor_code), (WindowsVersion::Other(0x0A00_000F), 1));
+        assert_eq!(transfers[1].decoded, Some(Decoded::DescriptorSet(set)));
+    }
+
+    #[test]
+    fn problems() {
+        let bos = bos();
+        let set_bytes = owned::DescriptorSet::from(&EXAMPLE_SET).to_bytes().unwrap();
+        let mut os_string = vec![18, 3];
+        os_string.extend("MSFT100".encode_utf16().flat_map(u16::to_le_bytes));
+        os_string.extend([0x20, 0]);
+        let packets: Vec<_> = [
+            transfer(1, BOS_SETUP, 0, bos.len(), &bos),
+            transfer(2, set_setup(set_bytes.len()), 0, 100, &set_bytes[..100]),
+            transfer(3, set_setup(set_bytes.len()), 0, set_bytes.len(), &set_bytes[..50]),
+            transfer(4, [0xc0, 2, 0, 0, 7, 0, 0x10, 0], -32, 0, &[]),
+            // Vendor requests with wIndex 4 are only MS OS 1.0 requests with the advertised vendor code
+            transfer(5, [0xc0, 0x20, 0, 0, 4, 0, 0x10, 0], 0, 0, &[]),
+            transfer(6, [0x80, 6, 0xee, 3, 0, 0, 0xff, 0], 0, 18, &os_string),
+            transfer(7, [0xc0, 0x21, 0, 0, 4, 0, 0x10, 0], 0, 0, &[]),
+            transfer(8, [0xc0, 0x20, 0, 0, 4, 0, 0x10, 0], 0, 16, &[0x28, 0, 0, 0, 0, 1, 4, 0, 1, 0, 0, 0, 0, 0, 0, 0]),
+        ].into_iter().flatten().collect();
+
+        let transfers = analyze(&pcap_file(&packets)).unwrap();
+        let problems: Vec<_> = transfers.iter().map(|t| t.problems.clone()).collect();
+        assert_eq!(problems, [
+            vec![],
+            vec![
+                Problem::LengthMismatch { field: "wMSOSDescriptorSetTotalLength", expected: set_bytes.len(), returned: 100 },
+                Problem::LengthMismatch { field: "descriptor set wTotalLength", expected: set_bytes.len(), returned: 100 },
+            ],
+            vec![Problem::Truncated { captured: 50, returned: set_bytes.len() }],
+            vec![Problem::Failed(-32)],
+            vec![],
+            vec![],
+        ]);
+        assert_eq!(transfers[4].decoded, Some(Decoded::OsString { vendor_code: 0x20 }));
+        assert_eq!((transfers[5].kind, transfers[5].setup.request), (TransferKind::Os10Descriptor, 0x20));
+        assert_eq!(format!("{}", problems[1][0]),
+            format!("wMSOSDescriptorSetTotalLength implies {} bytes but 100 were returned", set_bytes.len()));
+    }
+
+    #[test]
+    fn invalid_captures() {
+        assert_eq!(analyze(&[]), Err(Error::UnknownFormat));
+        assert_eq!(analyze(b"not a capture file"), Err(Error::UnknownFormat));
+        let mut pcap = pcap_file(&[urb(1, b'S', Some(BOS_SETUP), 0, 0xff, &[])]);
+        pcap.pop();
+        assert_eq!(analyze(&pcap), Err(Error::UnexpectedEnd { offset: 24 }));
+        pcap[23] = 1;
+        assert_eq!(analyze(&pcap), Err(Error::NoUsbmon));
+        let mut pcapng = pcapng_file(&[]);
+        pcapng[4] = 13;
+        assert_eq!(analyze(&pcapng), Err(Error::InvalidBlock { offset: 0 }));
+    }
+}
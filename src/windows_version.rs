@@ -7,7 +7,9 @@
 /// <https://learn.microsoft.com/en-us/windows/win32/winprog/using-the-windows-headers?redirectedfrom=MSDN#macros-for-conditional-declarations>
 /// <https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdm/nf-wdm-rtlisntddiversionavailable#parameters>
 #[repr(u32)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(missing_docs)]
 pub enum WindowsVersion {
     Win4 = 0x04000000,
@@ -63,8 +65,20 @@ pub enum WindowsVersion {
     Win10Fe = 0x0A00000A,
     /// Windows 10 21H2
     Win10Co = 0x0A00000B,
+    /// Value that is not one of the defined versions, e.g. a newer Windows version
+    ///
+    /// Compares equal to the defined version with the same value.
+    Other(u32),
 }
 
+impl PartialEq for WindowsVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.value() == other.value()
+    }
+}
+
+impl Eq for WindowsVersion {}
+
 #[allow(missing_docs)]
 impl WindowsVersion {
     /// Minimal version that can be used in Microsoft OS 2.0 Descriptors
@@ -86,13 +100,58 @@ impl WindowsVersion {
 
     pub const WINTHRESHOLD: Self = Self::Win10;
 
+    /// All defined versions
+    pub const ALL: [Self; 36] = [
+        Self::Win4, Self::Win2k, Self::Win2KSp1, Self::Win2KSp2, Self::Win2KSp3, Self::Win2KSp4,
+        Self::WinXp, Self::WinXpSp1, Self::WinXpSp2, Self::WinXpSp3, Self::WinXpSp4,
+        Self::WS03, Self::WS03Sp1, Self::WS03Sp2, Self::WS03Sp3, Self::WS03Sp4,
+        Self::Win6, Self::Win6Sp1, Self::Win6Sp2, Self::Win6Sp3, Self::Win6Sp4,
+        Self::Win7, Self::Win8, Self::WinBlue,
+        Self::Win10, Self::Win10Th2, Self::Win10Rs1, Self::Win10Rs2, Self::Win10Rs3, Self::Win10Rs4,
+        Self::Win10Rs5, Self::Win1019h1, Self::Win10Vb, Self::Win10Mn, Self::Win10Fe, Self::Win10Co,
+    ];
+
+    /// Get version from its NTDDI value, if it is one of the defined versions
+    pub const fn from_u32(value: u32) -> Option<Self> {
+        let mut i = 0;
+        while i < Self::ALL.len() {
+            if Self::ALL[i].value() == value {
+                return Some(Self::ALL[i]);
+            }
+            i += 1;
+        }
+        None
+    }
+
+    /// Get version from its NTDDI value, [`Self::Other`] if it is not one of the defined versions
+    pub const fn from_value(value: u32) -> Self {
+        match Self::from_u32(value) {
+            Some(version) => version,
+            None => Self::Other(value),
+        }
+    }
+
+    /// Get NTDDI value of the version
+    pub const fn value(&self) -> u32 {
+        match self {
+            Self::Other(value) => *value,
+            // SAFETY: enum with primitive representation starts with its `u32` discriminant,
+            // which is the NTDDI value for all variants other than `Other`
+            _ => unsafe { *(self as *const Self).cast::<u32>() },
+        }
+    }
+
     pub(crate) const fn bytes(&self) -> [u8; 4] {
         self.check_minimal();
-        (*self as u32).to_le_bytes()
+        self.value().to_le_bytes()
+    }
+
+    pub(crate) const fn is_allowed(&self) -> bool {
+        self.value() >= Self::MINIMAL.value()
     }
 
     pub(crate) const fn check_minimal(&self) {
-        if (*self as u32) < (Self::MINIMAL as u32) {
+        if !self.is_allowed() {
             panic!("Minimal version allowed in Microsoft OS 2.0 Descriptors is Self::MINIMAL (WinBlue = Windows 8.1)");
         }
     }